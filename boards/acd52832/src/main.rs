@@ -66,7 +66,11 @@ pub struct Platform {
         VirtualMuxAlarm<'static, Rtc<'static>>,
     >,
     button: &'static capsules::button::Button<'static, nrf52832::gpio::GPIOPin<'static>>,
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf52832::rtc::Rtc<'static>>,
+    >,
     gpio: &'static capsules::gpio::GPIO<'static, nrf52832::gpio::GPIOPin<'static>>,
     led: &'static capsules::led::LedDriver<
         'static,
@@ -329,8 +333,9 @@ pub unsafe fn main() {
         board_kernel,
         capsules::console::DRIVER_NUM,
         uart_mux,
+        mux_alarm,
     )
-    .finalize(());
+    .finalize(components::console_component_helper!(nrf52832::rtc::Rtc));
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
 
@@ -45,7 +45,11 @@ pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 /// A structure representing this platform that holds references to all
 /// capsules for this platform.
 struct ArtyE21 {
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, sifive::clint::Clint<'static>>,
+    >,
     gpio: &'static capsules::gpio::GPIO<'static, arty_e21_chip::gpio::GpioPin<'static>>,
     alarm: &'static capsules::alarm::AlarmDriver<
         'static,
@@ -158,19 +162,20 @@ pub unsafe fn main() {
     )
     .finalize(());
 
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules::console::DRIVER_NUM,
-        uart_mux,
-    )
-    .finalize(());
-
     // Create a shared virtualization mux layer on top of a single hardware
     // alarm.
     let mux_alarm = static_init!(
         MuxAlarm<'static, sifive::clint::Clint>,
         MuxAlarm::new(&peripherals.machinetimer)
     );
+
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules::console::DRIVER_NUM,
+        uart_mux,
+        mux_alarm,
+    )
+    .finalize(components::console_component_helper!(sifive::clint::Clint));
     hil::time::Alarm::set_alarm_client(&peripherals.machinetimer, mux_alarm);
 
     // Alarm
@@ -144,7 +144,11 @@ pub struct Platform {
         capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf52::rtc::Rtc<'static>>,
     >,
     ieee802154_radio: &'static capsules::ieee802154::RadioDriver<'static>,
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf52::rtc::Rtc<'static>>,
+    >,
     proximity: &'static capsules::proximity::ProximitySensor<'static>,
     gpio: &'static capsules::gpio::GPIO<'static, nrf52::gpio::GPIOPin<'static>>,
     led: &'static capsules::led::LedDriver<
@@ -469,8 +473,9 @@ pub unsafe fn main() {
         board_kernel,
         capsules::console::DRIVER_NUM,
         uart_mux,
+        mux_alarm,
     )
-    .finalize(());
+    .finalize(components::console_component_helper!(nrf52::rtc::Rtc));
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
 
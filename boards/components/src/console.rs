@@ -13,20 +13,54 @@
 //! let uart_mux = UartMuxComponent::new(&sam4l::usart::USART3,
 //!                                      115200,
 //!                                      deferred_caller).finalize(());
-//! let console = ConsoleComponent::new(board_kernel, uart_mux).finalize(());
+//! let console = ConsoleComponent::new(board_kernel, uart_mux, mux_alarm)
+//!     .finalize(components::console_component_helper!(sam4l::ast::Ast));
 //! ```
+//!
+//! The console's transmit and receive buffers default to
+//! `console::DEFAULT_BUF_LEN` bytes. To use a different size, pass it as the
+//! second argument to the helper:
+//!
+//! ```rust
+//! let console = ConsoleComponent::new(board_kernel, uart_mux, mux_alarm)
+//!     .finalize(components::console_component_helper!(sam4l::ast::Ast, 256));
+//! ```
+//!
+//! Either way the console has type `ConsoleComponentDefault<A>`.
 // Author: Philip Levis <pal@cs.stanford.edu>
 // Last modified: 1/08/2020
 
+use core::mem::MaybeUninit;
+
 use capsules::console;
+use capsules::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules::virtual_uart::{MuxUart, UartDevice};
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::dynamic_deferred_call::DynamicDeferredCall;
 use kernel::hil;
+use kernel::hil::time::Alarm;
 use kernel::hil::uart;
-use kernel::static_init;
+use kernel::{static_init, static_init_half};
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! console_component_helper {
+    ($A:ty $(,)?) => {{
+        $crate::console_component_helper!($A, capsules::console::DEFAULT_BUF_LEN)
+    };};
+    ($A:ty, $N:expr $(,)?) => {{
+        use capsules::virtual_alarm::VirtualMuxAlarm;
+        use core::mem::MaybeUninit;
+        static mut ALARM: MaybeUninit<VirtualMuxAlarm<'static, $A>> = MaybeUninit::uninit();
+        static mut CONSOLE: MaybeUninit<$crate::console::ConsoleComponentDefault<$A>> =
+            MaybeUninit::uninit();
+        static mut WRITE_BUF: [u8; $N] = [0; $N];
+        static mut READ_BUF: [u8; $N] = [0; $N];
+        (&mut ALARM, &mut CONSOLE, &mut WRITE_BUF, &mut READ_BUF)
+    };};
+}
 
 pub struct UartMuxComponent {
     uart: &'static dyn uart::Uart<'static>,
@@ -74,48 +108,75 @@ impl Component for UartMuxComponent {
     }
 }
 
-pub struct ConsoleComponent {
+/// The console a `ConsoleComponent` builds on alarm `A`. Its type does not
+/// depend on the buffer size, so boards can name it in their platform struct
+/// whichever size they pass to `console_component_helper!` (by default
+/// `console::DEFAULT_BUF_LEN`).
+pub type ConsoleComponentDefault<A> =
+    console::Console<'static, UartDevice<'static>, VirtualMuxAlarm<'static, A>>;
+
+pub struct ConsoleComponent<A: 'static + Alarm<'static>> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
     uart_mux: &'static MuxUart<'static>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
 }
 
-impl ConsoleComponent {
+impl<A: 'static + Alarm<'static>> ConsoleComponent<A> {
     pub fn new(
         board_kernel: &'static kernel::Kernel,
         driver_num: usize,
         uart_mux: &'static MuxUart,
-    ) -> ConsoleComponent {
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> ConsoleComponent<A> {
         ConsoleComponent {
             board_kernel: board_kernel,
             driver_num: driver_num,
             uart_mux: uart_mux,
+            alarm_mux: alarm_mux,
         }
     }
 }
 
-impl Component for ConsoleComponent {
-    type StaticInput = ();
-    type Output = &'static console::Console<'static>;
+impl<A: 'static + Alarm<'static>> Component for ConsoleComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<ConsoleComponentDefault<A>>,
+        &'static mut [u8],
+        &'static mut [u8],
+    );
+    type Output = &'static ConsoleComponentDefault<A>;
 
-    unsafe fn finalize(self, _s: Self::StaticInput) -> Self::Output {
+    unsafe fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
         // Create virtual device for console.
         let console_uart = static_init!(UartDevice, UartDevice::new(self.uart_mux, true));
         console_uart.setup();
+        console_uart.set_priority(console::UART_PRIORITY);
+
+        let console_alarm = static_init_half!(
+            static_buffer.0,
+            VirtualMuxAlarm<'static, A>,
+            VirtualMuxAlarm::new(self.alarm_mux)
+        );
+        console_alarm.setup();
 
-        let console = static_init!(
-            console::Console<'static>,
+        let console = static_init_half!(
+            static_buffer.1,
+            ConsoleComponentDefault<A>,
             console::Console::new(
                 console_uart,
-                &mut console::WRITE_BUF,
-                &mut console::READ_BUF,
+                console_alarm,
+                self.uart_mux.speed(),
+                static_buffer.2,
+                static_buffer.3,
                 self.board_kernel.create_grant(self.driver_num, &grant_cap)
             )
         );
         hil::uart::Transmit::set_transmit_client(console_uart, console);
         hil::uart::Receive::set_receive_client(console_uart, console);
+        console_alarm.set_alarm_client(console);
 
         console
     }
@@ -64,6 +64,9 @@ impl Component for DebugWriterComponent {
         // Create virtual device for kernel debug.
         let debugger_uart = static_init!(UartDevice, UartDevice::new(self.uart_mux, false));
         debugger_uart.setup();
+        // Kernel debug output goes ahead of other users of the mux, so that
+        // panics and debug messages stay legible under heavy app logging.
+        debugger_uart.set_priority(u8::MAX);
         let ring_buffer = static_init!(RingBuffer<'static, u8>, RingBuffer::new(internal_buf));
         let debugger = static_init!(
             kernel::debug::DebugWriter,
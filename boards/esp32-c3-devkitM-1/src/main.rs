@@ -67,7 +67,11 @@ pub static mut STACK_MEMORY: [u8; 0x900] = [0; 0x900];
 /// capsules for this platform. We've included an alarm and console.
 struct Esp32C3Board {
     gpio: &'static capsules::gpio::GPIO<'static, esp32::gpio::GpioPin<'static>>,
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, esp32::timg::TimG<'static>>,
+    >,
     alarm: &'static capsules::alarm::AlarmDriver<
         'static,
         VirtualMuxAlarm<'static, esp32::timg::TimG<'static>>,
@@ -238,8 +242,9 @@ unsafe fn setup() -> (
         board_kernel,
         capsules::console::DRIVER_NUM,
         uart_mux,
+        mux_alarm,
     )
-    .finalize(());
+    .finalize(components::console_component_helper!(esp32::timg::TimG));
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
 
@@ -53,7 +53,11 @@ pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 /// A structure representing this platform that holds references to all
 /// capsules for this platform.
 struct Hail {
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >,
     gpio: &'static capsules::gpio::GPIO<'static, sam4l::gpio::GPIOPin<'static>>,
     alarm: &'static capsules::alarm::AlarmDriver<
         'static,
@@ -297,8 +301,9 @@ pub unsafe fn main() {
         board_kernel,
         capsules::console::DRIVER_NUM,
         uart_mux,
+        mux_alarm,
     )
-    .finalize(());
+    .finalize(components::console_component_helper!(sam4l::ast::Ast));
     let process_console = components::process_console::ProcessConsoleComponent::new(
         board_kernel,
         uart_mux,
@@ -52,7 +52,11 @@ struct HiFive1 {
         LedLow<'static, sifive::gpio::GpioPin<'static>>,
         3,
     >,
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, sifive::clint::Clint<'static>>,
+    >,
     lldb: &'static capsules::low_level_debug::LowLevelDebug<
         'static,
         capsules::virtual_uart::UartDevice<'static>,
@@ -234,8 +238,9 @@ pub unsafe fn main() {
         board_kernel,
         capsules::console::DRIVER_NUM,
         uart_mux,
+        mux_alarm,
     )
-    .finalize(());
+    .finalize(components::console_component_helper!(sifive::clint::Clint));
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
 
@@ -74,7 +74,11 @@ struct Imxrt1050EVKB {
         VirtualMuxAlarm<'static, imxrt1050::gpt::Gpt1<'static>>,
     >,
     button: &'static capsules::button::Button<'static, imxrt1050::gpio::Pin<'static>>,
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, imxrt1050::gpt::Gpt1<'static>>,
+    >,
     gpio: &'static capsules::gpio::GPIO<'static, imxrt1050::gpio::Pin<'static>>,
     ipc: kernel::ipc::IPC<NUM_PROCS, NUM_UPCALLS_IPC>,
     led: &'static capsules::led::LedDriver<
@@ -324,13 +328,6 @@ pub unsafe fn main() {
     let process_management_capability =
         create_capability!(capabilities::ProcessManagementCapability);
 
-    // Setup the console.
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules::console::DRIVER_NUM,
-        lpuart_mux,
-    )
-    .finalize(());
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(lpuart_mux).finalize(());
 
@@ -363,6 +360,15 @@ pub unsafe fn main() {
         components::alarm_mux_component_helper!(imxrt1050::gpt::Gpt1),
     );
 
+    // Setup the console.
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules::console::DRIVER_NUM,
+        lpuart_mux,
+        mux_alarm,
+    )
+    .finalize(components::console_component_helper!(imxrt1050::gpt::Gpt1));
+
     let alarm = components::alarm::AlarmDriverComponent::new(
         board_kernel,
         capsules::alarm::DRIVER_NUM,
@@ -49,7 +49,11 @@ struct MspExp432P401R {
         kernel::hil::led::LedHigh<'static, msp432::gpio::IntPin<'static>>,
         3,
     >,
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, msp432::timer::TimerA<'static>>,
+    >,
     button: &'static capsules::button::Button<'static, msp432::gpio::IntPin<'static>>,
     gpio: &'static capsules::gpio::GPIO<'static, msp432::gpio::IntPin<'static>>,
     alarm: &'static capsules::alarm::AlarmDriver<
@@ -339,13 +343,6 @@ pub unsafe fn main() {
     )
     .finalize(());
 
-    // Setup the console.
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules::console::DRIVER_NUM,
-        uart_mux,
-    )
-    .finalize(());
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
 
@@ -354,6 +351,15 @@ pub unsafe fn main() {
     let mux_alarm = components::alarm::AlarmMuxComponent::new(timer0).finalize(
         components::alarm_mux_component_helper!(msp432::timer::TimerA),
     );
+
+    // Setup the console.
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules::console::DRIVER_NUM,
+        uart_mux,
+        mux_alarm,
+    )
+    .finalize(components::console_component_helper!(msp432::timer::TimerA));
     let alarm = components::alarm::AlarmDriverComponent::new(
         board_kernel,
         capsules::alarm::DRIVER_NUM,
@@ -437,6 +443,7 @@ pub unsafe fn main() {
         systick: cortexm4::systick::SysTick::new_with_calibration(48_000_000),
         wdt: &peripherals.wdt,
     };
+    kernel::debug::set_panic_watchdog(&peripherals.wdt);
 
     debug!("Initialization complete. Entering main loop");
 
@@ -42,6 +42,12 @@ static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS]
 // Static reference to chip for panic dumps.
 static mut CHIP: Option<&'static stm32f303xc::chip::Stm32f3xx<Stm32f3xxDefaultPeripherals>> = None;
 
+// The only app allowed to claim watchdog pre-timeout notifications.
+const WATCHDOG_NOTIFY_APP: &str = "watchdog_notify";
+
+struct WatchdogNotifyCapability;
+unsafe impl capabilities::ProcessManagementCapability for WatchdogNotifyCapability {}
+
 // How should the kernel respond when a process faults.
 const FAULT_RESPONSE: kernel::process::PanicFaultPolicy = kernel::process::PanicFaultPolicy {};
 
@@ -53,7 +59,11 @@ pub static mut STACK_MEMORY: [u8; 0x1400] = [0; 0x1400];
 /// A structure representing this platform that holds references to all
 /// capsules for this platform.
 struct STM32F3Discovery {
-    console: &'static capsules::console::Console<'static>,
+    console: &'static capsules::console::Console<
+        'static,
+        capsules::virtual_uart::UartDevice<'static>,
+        capsules::virtual_alarm::VirtualMuxAlarm<'static, stm32f303xc::tim2::Tim2<'static>>,
+    >,
     ipc: kernel::ipc::IPC<NUM_PROCS, NUM_UPCALLS_IPC>,
     gpio: &'static capsules::gpio::GPIO<'static, stm32f303xc::gpio::Pin<'static>>,
     led: &'static capsules::led::LedDriver<
@@ -72,6 +82,11 @@ struct STM32F3Discovery {
     >,
     adc: &'static capsules::adc::AdcVirtualized<'static>,
     nonvolatile_storage: &'static capsules::nonvolatile_storage_driver::NonvolatileStorage<'static>,
+    watchdog_notify: &'static capsules::watchdog_notify::WatchdogNotify<
+        'static,
+        wdt::WindoWdg<'static>,
+        WatchdogNotifyCapability,
+    >,
 
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
@@ -97,6 +112,7 @@ impl SyscallDriverLookup for STM32F3Discovery {
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
             capsules::adc::DRIVER_NUM => f(Some(self.adc)),
             capsules::nonvolatile_storage_driver::DRIVER_NUM => f(Some(self.nonvolatile_storage)),
+            capsules::watchdog_notify::DRIVER_NUM => f(Some(self.watchdog_notify)),
             _ => f(None),
         }
     }
@@ -422,13 +438,6 @@ pub unsafe fn main() {
     let process_management_capability =
         create_capability!(capabilities::ProcessManagementCapability);
 
-    // Setup the console.
-    let console = components::console::ConsoleComponent::new(
-        board_kernel,
-        capsules::console::DRIVER_NUM,
-        uart_mux,
-    )
-    .finalize(());
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux).finalize(());
 
@@ -515,6 +524,17 @@ pub unsafe fn main() {
         components::alarm_mux_component_helper!(stm32f303xc::tim2::Tim2),
     );
 
+    // Setup the console.
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules::console::DRIVER_NUM,
+        uart_mux,
+        mux_alarm,
+    )
+    .finalize(components::console_component_helper!(
+        stm32f303xc::tim2::Tim2
+    ));
+
     let alarm = components::alarm::AlarmDriverComponent::new(
         board_kernel,
         capsules::alarm::DRIVER_NUM,
@@ -766,6 +786,27 @@ pub unsafe fn main() {
     ));
     let _ = process_console.start();
 
+    // WATCHDOG NOTIFY
+    let watchdog_notify = static_init!(
+        capsules::watchdog_notify::WatchdogNotify<
+            'static,
+            wdt::WindoWdg<'static>,
+            WatchdogNotifyCapability,
+        >,
+        capsules::watchdog_notify::WatchdogNotify::new(
+            &peripherals.watchdog,
+            board_kernel.create_grant(
+                capsules::watchdog_notify::DRIVER_NUM,
+                &memory_allocation_capability
+            ),
+            board_kernel,
+            WATCHDOG_NOTIFY_APP,
+            WatchdogNotifyCapability,
+        )
+    );
+    kernel::hil::watchdog::PreTimeout::set_client(&peripherals.watchdog, watchdog_notify);
+    cortexm4::nvic::Nvic::new(stm32f303xc::nvic::WWDG).enable();
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&PROCESSES)
         .finalize(components::rr_component_helper!(NUM_PROCS));
 
@@ -786,6 +827,7 @@ pub unsafe fn main() {
         temp: temp,
         adc: adc_syscall,
         nonvolatile_storage: nonvolatile_storage,
+        watchdog_notify: watchdog_notify,
 
         scheduler,
         systick: cortexm4::systick::SysTick::new(),
@@ -833,6 +875,7 @@ pub unsafe fn main() {
 
     // Uncomment this to enable the watchdog
     peripherals.watchdog.enable();
+    kernel::debug::set_panic_watchdog(&peripherals.watchdog);
 
     //Uncomment to run multi alarm test
     /*components::test::multi_alarm_test::MultiAlarmTestComponent::new(mux_alarm)
@@ -3,22 +3,31 @@
 //! Setup
 //! -----
 //!
-//! You need a device that provides the `hil::uart::UART` trait.
+//! You need a device that provides the `hil::uart::Uart` traits (`Transmit`,
+//! `Receive` and `Configure`). `Console` is generic over that device, so it
+//! can sit directly on a UART, on a `virtual_uart::UartDevice`, or on a mock.
 //!
 //! ```rust
 //! # use kernel::static_init;
 //! # use capsules::console::Console;
 //!
 //! let console = static_init!(
-//!     Console<usart::USART>,
+//!     Console<'static, usart::USART, VirtualMuxAlarm<'static, ast::Ast>>,
 //!     Console::new(&usart::USART0,
+//!                  console_alarm,
 //!                  115200,
 //!                  &mut console::WRITE_BUF,
 //!                  &mut console::READ_BUF,
 //!                  board_kernel.create_grant(&grant_cap)));
-//! hil::uart::UART::set_client(&usart::USART0, console);
+//! hil::uart::Transmit::set_transmit_client(&usart::USART0, console);
+//! hil::uart::Receive::set_receive_client(&usart::USART0, console);
+//! console_alarm.set_alarm_client(console);
 //! ```
 //!
+//! The baud rate passed to `new` must be the one the UART was configured
+//! with; the console starts from it (with 8N1 framing and no flow control)
+//! when apps reconfigure the line.
+//!
 //! Usage
 //! -----
 //!
@@ -33,16 +42,87 @@
 //! command(CONSOLE_DRIVER_NUM, 1, len_to_write_in_bytes)
 //! ```
 //!
-//! When the buffer has been written successfully, the buffer is released from
-//! the driver. Successive writes must call `allow` each time a buffer is to be
-//! written.
+//! A write that the `command` accepts produces exactly one write callback,
+//! carrying the number of bytes written in `r0`, even if the UART fails
+//! partway through. In that case `r1` carries the error; it is `0` for a
+//! write that completed normally. A write that the `command` rejects, or
+//! that the app drops with command `20` before it starts, produces no
+//! callback.
+//!
+//! The write callback fires once the UART has handed back the last
+//! transmission of the write, while the console may already be sending other
+//! output. To wait until the console has nothing left on the line, e.g.
+//! before the board sleeps, an app can flush with command `26`: its callback
+//! waits for the app's write to finish and for the console to have nothing
+//! left to send, including other apps' and the kernel's output.
+//!
+//! The buffer stays shared with the driver after the write completes, until
+//! the app replaces or revokes it with another `allow`. Successive writes from
+//! the same buffer therefore only need to issue the `command` again; `allow`
+//! is only required when switching to a different buffer.
+//!
+//! The read buffer (read-write `allow_num` 1) has the same lifecycle. The
+//! console never takes it from the app: received bytes are copied into it
+//! when they arrive, and it stays shared after the read callback, so an app
+//! can issue getnstr again and again against one `allow`. Because the copy
+//! happens on arrival, a buffer swapped in while a read is in progress gets
+//! the rest of that read at the same offset, and a shrunk or revoked one
+//! makes the read end with `SIZE` or `NOMEM` (see command `2`).
+//!
+//! To send a message built from several fragments without copying them into
+//! one buffer, an app can also share up to `GATHER_BUFFERS` more buffers with
+//! read-only `allow_num` 5, 6 and so on, and send them back to back as one
+//! write with command `21`.
+//!
+//! An app can have a write and a read outstanding at the same time, e.g. by
+//! issuing putstr and getnstr back to back. They use separate buffers (the
+//! read-only and read-write buffers with `allow_num` 1) and separate
+//! callbacks, and the console tracks them independently, so neither waits
+//! for or disturbs the other. Bytes echoed for the read go out as kernel
+//! output between the transactions of the write, unless it is atomic, in
+//! which case they wait for it to finish.
+//!
+//! Commands that start a write or a read keep two failures apart. `BUSY`
+//! means the request conflicts with something in progress: the app's own
+//! write, a read by any app (the console receives for one app at a time), or
+//! another app's exclusive claim. Retrying later can succeed. `INVAL` means
+//! the buffer the request needs is empty, because the app never shared one,
+//! revoked it or shared a zero-length one, and retrying will not help until
+//! it calls `allow`. Errors that happen once a write or
+//! read is under way, e.g. when a write cannot be continued, are reported in
+//! its callback rather than by the `command`.
+//!
+//! Capabilities
+//! ------------
+//!
+//! Command `41` returns a bitmask of the optional features the console
+//! offers on this board, so that userspace libraries can fall back when one
+//! is missing. Bit positions are stable; new features only take new bits.
+//!
+//! | Bit | Constant            | Feature                                      |
+//! |-----|---------------------|----------------------------------------------|
+//! | 0   | `CAP_CRC_FRAMING`   | CRC-16 framing of writes and reads (`10`)    |
+//! | 1   | `CAP_CONTINUOUS_RX` | Continuous receive into a ring (`23`, `44`)  |
+//! | 2   | `CAP_STREAM_READ`   | Streaming reads (`33`)                       |
+//! | 3   | `CAP_HEX_DUMP`      | `HEX_DUMP` and `HEX_DUMP_SPACED` write flags |
+//! | 4   | `CAP_RX_PAUSE`      | Pausing and resuming reads (`39`, `40`)      |
+//! | 5   | `CAP_BAUD_QUERY`    | The UART reports achievable baud rates (`4`) |
+//! | 6   | `CAP_RS485`         | RS-485 half-duplex pins are wired (`36`)     |
+//!
+//! Line features that depend on the UART, such as flow control, 9-bit data
+//! or loopback, are not in the mask: the console cannot tell whether they
+//! work without reconfiguring the line, so their commands report `NOSUPPORT`
+//! instead.
 
+use core::cell::Cell;
 use core::{cmp, mem};
 
-use kernel::grant::Grant;
+use kernel::grant::{Grant, GrantUpcallTable};
+use kernel::hil::gpio;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks, Frequency, Ticks};
 use kernel::hil::uart;
 use kernel::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
-use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::processbuffer::{ReadableProcessBuffer, ReadableProcessSlice, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::{ErrorCode, ProcessId};
@@ -54,129 +134,2106 @@ pub const DRIVER_NUM: usize = driver::NUM::Console as usize;
 #[derive(Default)]
 pub struct App {
     write_buffer: ReadOnlyProcessBuffer,
+    gather_buffers: [ReadOnlyProcessBuffer; GATHER_BUFFERS], // Allowed with `allow_num` 5 onwards.
+    write_segments: usize, // How many allowed buffers, in `allow_num` order, make up the current write.
     write_len: usize,
     write_remaining: usize, // How many bytes didn't fit in the buffer and still need to be printed.
+    write_fill: Option<u8>, // Byte the current write repeats, instead of reading write buffers.
+    write_newline: bool,    // The current write ends in a '\n' the driver appends.
+    write_hex: Option<usize>, // Output bytes per app byte when the write goes out as hex.
     pending_write: bool,
+    cancel_write: bool, // Drop the rest of the write once the UART returns the TX buffer.
+    tx_rate_limit: usize, // Output rate limit in bytes per second; 0 means unlimited.
+    tx_tokens: usize,   // Bytes the app may still send before it is throttled.
+    tx_refilled_at: u32, // Low 32 bits of the alarm time `tx_tokens` was last topped up.
+    crc_poly: Option<u16>, // CRC-16 polynomial for framing; `None` when framing is off.
+    tx_crc: u16,        // CRC over the bytes of the current write sent so far.
+    tx_crc_pending: bool, // The current write still owes its CRC trailer.
+    tx_chunk_len: usize, // App bytes in the transaction in flight.
+    tx_chunk_crc: u16,  // `tx_crc` before the transaction in flight.
+    atomic_write: bool, // Hold the transmitter for the current write until it is done.
+    flush_pending: bool, // Issue the flush callback once the current write is done.
+    tx_priority: usize, // Queued writes from apps with a higher priority go first.
 
     read_buffer: ReadWriteProcessBuffer,
     read_len: usize,
+    read_offset: usize, // How many bytes of the current read are already in read_buffer.
+    rx_idle_ms: usize,  // Idle period that triggers a line event; 0 disables it.
+    rx_idle_chars: usize, // Idle period in character-times, used instead of `rx_idle_ms` if set.
+    read_delimiter: Option<u8>, // Byte that ends the current read early, if any.
+    echo: bool,         // Echo bytes received for this app back out of the UART.
+    read_byte: bool,    // The current read is a single byte returned in the callback.
+    read_stream: bool,  // The current read hands over bytes as they arrive until aborted.
+    rx_dropped_seen: usize, // Value of `rx_dropped` when the app last queried it.
+    rx_timestamps: bool, // Report when each read completed in `r2` of the read callback.
+    rx_last_error: Option<uart::Error>, // Error that ended a read since the last successful receive.
+}
+
+impl App {
+    /// The allowed buffers making up the current write, in order.
+    fn write_data(&self) -> impl Iterator<Item = &ReadOnlyProcessBuffer> {
+        core::iter::once(&self.write_buffer)
+            .chain(self.gather_buffers.iter())
+            .take(self.write_segments)
+    }
+
+    /// Whether the write in progress, if any, reads from the write buffer at
+    /// `index` in `write_data` order: `0` for `allow_num` 1, then the gather
+    /// buffers.
+    fn write_uses(&self, index: usize) -> bool {
+        self.write_len > 0 && index < self.write_segments
+    }
+
+    /// Length of the write's output for `input` bytes taken from the app's
+    /// buffers, after hex expansion and the appended newline, if any.
+    fn output_len(&self, input: usize) -> usize {
+        let expanded = match self.write_hex {
+            // Separators only go between pairs.
+            Some(width) => (input * width).saturating_sub(width - 2),
+            None => input,
+        };
+        expanded + self.write_newline as usize
+    }
+
+    /// How the write in progress lays out its output.
+    fn write_format(&self) -> WriteFormat {
+        WriteFormat {
+            len: self.write_len,
+            fill: self.write_fill,
+            hex: self.write_hex,
+            newline: self.write_newline,
+        }
+    }
+
+    /// Trims the write to the `available` bytes of output its buffers hold,
+    /// if an app buffer shrank under it, but never below what was already
+    /// sent.
+    fn clamp_write(&mut self, available: usize) {
+        if self.write_fill.is_none() && self.write_len > available {
+            let sent = self.write_len - self.write_remaining;
+            self.write_len = cmp::max(available, sent);
+            self.write_remaining = self.write_len - sent;
+        }
+    }
+
+    /// Ends the write early: drops whatever has not been handed to the UART
+    /// yet, plus `unsent` bytes of the last transaction, from the reported
+    /// length.
+    fn truncate_write(&mut self, unsent: usize) {
+        self.write_len -= self.write_remaining + unsent;
+        self.write_remaining = 0;
+        self.tx_crc_pending = false;
+    }
+
+    /// Charges the write for a transaction of `len` bytes carrying
+    /// `payload_len` of its bytes, or its CRC trailer if `trailer` is set,
+    /// after which `crc` covers everything sent. Returns the rate limit
+    /// tokens taken, for `unwind_transaction`.
+    fn begin_transaction(
+        &mut self,
+        len: usize,
+        payload_len: usize,
+        crc: u16,
+        trailer: bool,
+    ) -> usize {
+        self.write_remaining -= payload_len;
+        self.tx_chunk_len = payload_len;
+        self.tx_chunk_crc = self.tx_crc;
+        self.tx_crc = crc;
+        self.tx_crc_pending &= !trailer;
+        let tokens = cmp::min(self.tx_tokens, len);
+        self.tx_tokens -= tokens;
+        tokens
+    }
+
+    /// Undoes `begin_transaction` for a transaction the UART rejected, so
+    /// that it is sent again.
+    fn unwind_transaction(&mut self, trailer: bool, tokens: usize) {
+        self.write_remaining += self.tx_chunk_len;
+        self.tx_chunk_len = 0;
+        self.tx_crc = self.tx_chunk_crc;
+        self.tx_crc_pending |= trailer;
+        self.tx_tokens += tokens;
+    }
+
+    /// App bytes of the transaction in flight that the UART did not get out,
+    /// given the `tx_len` it reports having sent.
+    fn unsent(&self, tx_len: usize) -> usize {
+        self.tx_chunk_len.saturating_sub(tx_len)
+    }
+
+    /// Puts the last `unsent` bytes of the transaction in flight back into
+    /// the write, along with their share of the CRC and of the rate limit, so
+    /// that they are sent again. `buffer` holds the returned transaction.
+    fn requeue_unsent(&mut self, unsent: usize, buffer: &[u8]) {
+        if unsent == 0 {
+            return;
+        }
+        self.write_remaining += unsent;
+        self.tx_tokens += unsent;
+        let sent = self.tx_chunk_len - unsent;
+        if let Some(poly) = self.crc_poly {
+            self.tx_crc = crc16(poly, self.tx_chunk_crc, buffer.iter().take(sent).copied());
+        }
+    }
+
+    /// Whether RX idle detection is on for the app's reads.
+    fn detects_idle(&self) -> bool {
+        self.rx_idle_ms > 0 || self.rx_idle_chars > 0
+    }
+}
+
+/// Events delivered through the line event upcall (`subscribe_num` 3). The
+/// event kind is passed in `r0`.
+#[derive(Clone, Copy)]
+enum LineEvent {
+    /// The RX line has been idle for the app's configured period partway
+    /// through a read. `r1` carries the number of bytes received so far.
+    RxIdle = 1,
+    /// A read framed with a CRC completed, but the trailing CRC did not match
+    /// the payload. `r1` carries the payload length, as reported by the read
+    /// callback.
+    CrcMismatch = 2,
+}
+
+/// CRC-16 polynomial used for framing when the app does not pick one
+/// (CCITT, x^16 + x^12 + x^5 + 1).
+pub const DEFAULT_CRC_POLY: u16 = 0x1021;
+
+/// Number of write buffers an app can share in addition to the one with
+/// `allow_num` 1, for gather writes (command `21`).
+pub const GATHER_BUFFERS: usize = 3;
+const GATHER_ALLOW_MAX: usize = 4 + GATHER_BUFFERS;
+
+/// Flag in `arg2` of the putstr and gather write commands asking for the
+/// write to go out without other apps' output interleaved.
+pub const ATOMIC_WRITE: usize = 1;
+
+/// Flag in `arg2` of the putstr and gather write commands asking for a `\n`
+/// to be sent after the app's bytes.
+pub const APPEND_NEWLINE: usize = 1 << 1;
+
+/// Flag in `arg2` of the putstr and gather write commands asking for the
+/// app's bytes to be sent as pairs of lowercase hex digits.
+pub const HEX_DUMP: usize = 1 << 2;
+
+/// Like `HEX_DUMP`, with a space between consecutive pairs.
+pub const HEX_DUMP_SPACED: usize = 1 << 3;
+
+/// Flag in `arg2` of the getnstr command marking its low byte as a delimiter
+/// that ends the read.
+pub const READ_DELIMITER: usize = 1 << 8;
+
+/// Capability bits returned by command `41`; see the module documentation.
+pub const CAP_CRC_FRAMING: u32 = 1 << 0;
+pub const CAP_CONTINUOUS_RX: u32 = 1 << 1;
+pub const CAP_STREAM_READ: u32 = 1 << 2;
+pub const CAP_HEX_DUMP: u32 = 1 << 3;
+pub const CAP_RX_PAUSE: u32 = 1 << 4;
+pub const CAP_BAUD_QUERY: u32 = 1 << 5;
+pub const CAP_RS485: u32 = 1 << 6;
+
+/// Number of received bytes the console holds, with continuous receive on,
+/// until an app reads them.
+pub const RX_RING_LEN: usize = 64;
+
+/// Ring buffer for bytes received with continuous receive on. Bytes that
+/// arrive while it is full are dropped and counted.
+struct RxRing {
+    buf: [Cell<u8>; RX_RING_LEN],
+    head: Cell<usize>,
+    len: Cell<usize>,
+    dropped: Cell<usize>,
+    // UART error reported while no read was in progress.
+    error: Cell<Option<uart::Error>>,
+}
+
+impl RxRing {
+    fn new() -> RxRing {
+        const EMPTY: Cell<u8> = Cell::new(0);
+        RxRing {
+            buf: [EMPTY; RX_RING_LEN],
+            head: Cell::new(0),
+            len: Cell::new(0),
+            dropped: Cell::new(0),
+            error: Cell::new(None),
+        }
+    }
+
+    /// Queues `byte`. Returns `false` if the ring is full and it was
+    /// dropped.
+    fn push(&self, byte: u8) -> bool {
+        let len = self.len.get();
+        if len == RX_RING_LEN {
+            self.count_dropped();
+            false
+        } else {
+            self.buf[(self.head.get() + len) % RX_RING_LEN].set(byte);
+            self.len.set(len + 1);
+            true
+        }
+    }
+
+    fn pop(&self) -> Option<u8> {
+        let len = self.len.get();
+        if len == 0 {
+            return None;
+        }
+        let head = self.head.get();
+        self.head.set((head + 1) % RX_RING_LEN);
+        self.len.set(len - 1);
+        Some(self.buf[head].get())
+    }
+
+    /// Counts a received byte that was lost.
+    fn count_dropped(&self) {
+        self.dropped.set(self.dropped.get().wrapping_add(1));
+    }
+
+    /// Returns how many bytes were dropped since the last call.
+    fn take_dropped(&self) -> usize {
+        self.dropped.replace(0)
+    }
+
+    /// Keeps `error` for the next read, which no read was there to take.
+    fn set_error(&self, error: uart::Error) {
+        self.error.set(Some(error));
+    }
+
+    /// Returns the error kept since the last call, if any.
+    fn take_error(&self) -> Option<uart::Error> {
+        self.error.take()
+    }
+
+    /// Discards every queued byte.
+    fn clear(&self) {
+        self.len.set(0);
+    }
+}
+
+/// Number of received bytes that can wait to be echoed while the UART is
+/// busy transmitting.
+const ECHO_BUF_LEN: usize = 16;
+
+/// Length of the CRC trailer appended to and stripped from framed data.
+const CRC_LEN: usize = 2;
+
+/// Bytes sent and expected back by the loopback self-test (command `25`).
+/// They alternate bit patterns to catch stuck and shorted data lines.
+const LOOPBACK_PATTERN: [u8; 8] = [0x55, 0xAA, 0x00, 0xFF, 0x0F, 0xF0, 0x33, 0xCC];
+
+/// How long the loopback self-test waits for its bytes to come back.
+const LOOPBACK_TIMEOUT_MS: u32 = 100;
+
+/// The part of a write segment that belongs in a transaction carrying bytes
+/// `from..end` of the write, where the segment holds bytes
+/// `start..start + seg_len`. Returns the offset into the segment and the
+/// number of bytes to copy, or `None` if the segment lies outside the range.
+fn segment_span(start: usize, seg_len: usize, from: usize, end: usize) -> Option<(usize, usize)> {
+    let from = cmp::max(from, start);
+    let to = cmp::min(end, start + seg_len);
+    (from < to).then(|| (from - start, to - from))
 }
 
-pub static mut WRITE_BUF: [u8; 64] = [0; 64];
-pub static mut READ_BUF: [u8; 64] = [0; 64];
+/// A buffer a write takes its bytes from: one of the app's allowed buffers,
+/// or a plain slice in tests.
+trait WriteSegment {
+    fn segment_len(&self) -> usize;
+
+    /// Calls `fun` with the buffer's bytes, unless the app has gone away.
+    fn enter_data(&self, fun: &mut dyn FnMut(&ReadableProcessSlice));
+}
 
-pub struct Console<'a> {
-    uart: &'a dyn uart::UartData<'a>,
-    apps: Grant<App, 3>,
+impl WriteSegment for ReadOnlyProcessBuffer {
+    fn segment_len(&self) -> usize {
+        self.len()
+    }
+
+    fn enter_data(&self, fun: &mut dyn FnMut(&ReadableProcessSlice)) {
+        let _ = self.enter(|data| fun(data));
+    }
+}
+
+/// How a write lays out its output of `len` bytes: `fill` repeated, or the
+/// bytes of its segments back to back, as `hex` output bytes each if it is
+/// given, followed by a `\n` if `newline` is set.
+#[derive(Clone, Copy, Default)]
+struct WriteFormat {
+    len: usize,
+    fill: Option<u8>,
+    hex: Option<usize>,
+    newline: bool,
+}
+
+/// Builds the transaction carrying output bytes `sent..end` of a write laid
+/// out as `format` at the start of `buffer`, reading the app bytes behind
+/// them from `segments`, and returns its length. The transaction stops
+/// short if a segment has shrunk since the write started.
+fn build_chunk<'s, S: WriteSegment + ?Sized + 's>(
+    buffer: &mut [u8],
+    segments: impl Iterator<Item = &'s S>,
+    format: WriteFormat,
+    sent: usize,
+    end: usize,
+) -> usize {
+    if let Some(byte) = format.fill {
+        buffer[..end - sent].fill(byte);
+        return end - sent;
+    }
+    // An appended newline is the last byte of the write, after the app's
+    // bytes.
+    let payload_end = format.len - format.newline as usize;
+    let copy_end = cmp::min(end, payload_end);
+    let mut start = 0;
+    let mut len = 0;
+    for segment in segments {
+        segment.enter_data(&mut |data| match format.hex {
+            Some(width) => {
+                // Expand the app bytes behind the output range, which may
+                // start or end partway through a byte's pair.
+                let from = (sent + len) / width;
+                let to = (copy_end + width - 1) / width;
+                if let Some((offset, count)) = segment_span(start, data.len(), from, to) {
+                    let bytes = data[offset..offset + count].iter();
+                    for (i, byte) in bytes.enumerate() {
+                        let first = (start + offset + i) * width;
+                        for pos in first..first + width {
+                            if pos >= sent + len && pos < copy_end {
+                                buffer[len] = hex_char(byte.get(), pos - first);
+                                len += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            None => {
+                if let Some((offset, count)) = segment_span(start, data.len(), sent + len, copy_end)
+                {
+                    data[offset..offset + count].copy_to_slice(&mut buffer[len..len + count]);
+                    len += count;
+                }
+            }
+        });
+        start += segment.segment_len();
+    }
+    if format.newline && end > payload_end && sent + len == payload_end {
+        buffer[len] = b'\n';
+        len += 1;
+    }
+    len
+}
+
+/// Character number `index` of the hex dump of `byte`: its high and low
+/// digits, then a separating space.
+fn hex_char(byte: u8, index: usize) -> u8 {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    match index {
+        0 => DIGITS[(byte >> 4) as usize],
+        1 => DIGITS[(byte & 0xf) as usize],
+        _ => b' ',
+    }
+}
+
+/// Time one character takes on a line configured with `params`, in
+/// microseconds rounded up: a start bit, the data bits, a parity bit if any
+/// and the stop bits.
+fn char_time_us(params: uart::Parameters) -> u32 {
+    let parity_bits = match params.parity {
+        uart::Parity::None => 0,
+        uart::Parity::Odd | uart::Parity::Even => 1,
+    };
+    let stop_bits = match params.stop_bits {
+        uart::StopBits::One => 1,
+        uart::StopBits::Two => 2,
+    };
+    let bits = 1 + params.width as u64 + parity_bits + stop_bits;
+    let baud_rate = cmp::max(params.baud_rate, 1) as u64;
+    ((bits * 1_000_000 + baud_rate - 1) / baud_rate) as u32
+}
+
+/// CRC-16 over `data`, MSB first with no final XOR, continuing from `crc`.
+/// Starting from 0 with `DEFAULT_CRC_POLY` this is CRC-16/XMODEM.
+fn crc16(poly: u16, mut crc: u16, data: impl Iterator<Item = u8>) -> u16 {
+    for byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ poly
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Largest deviation from a requested baud rate, in parts per thousand, that
+/// command `4` still reports as achievable.
+pub const BAUD_RATE_TOLERANCE: u32 = 20;
+
+/// Longest gap, in microseconds, command `42` accepts between transmissions.
+/// The gap holds up every app's output and kernel messages, so no app can
+/// stall the line for longer than this per byte.
+pub const MAX_TX_GAP_US: u32 = 10_000;
+
+/// Transmit priority of the console's `UartDevice` when it shares a
+/// `MuxUart`. Kernel debug output runs at a higher priority so it is not
+/// starved by heavy app logging.
+pub const UART_PRIORITY: u8 = 0;
+
+/// Default length of the console's transmit and receive buffers. The console
+/// works with buffers of any length; boards that need larger writes or reads
+/// can pass their own.
+pub const DEFAULT_BUF_LEN: usize = 64;
+
+pub static mut WRITE_BUF: [u8; DEFAULT_BUF_LEN] = [0; DEFAULT_BUF_LEN];
+pub static mut READ_BUF: [u8; DEFAULT_BUF_LEN] = [0; DEFAULT_BUF_LEN];
+
+/// Whether the UART can generate `baud_rate`: it must be non-zero and, if
+/// the UART reported the `nearest` rate it can actually generate, within
+/// `BAUD_RATE_TOLERANCE` of it.
+fn baud_rate_result(baud_rate: u32, nearest: Result<u32, ErrorCode>) -> Result<u32, ErrorCode> {
+    if baud_rate == 0 {
+        return Err(ErrorCode::INVAL);
+    }
+    match nearest {
+        Ok(nearest) => {
+            let deviation = if nearest > baud_rate {
+                nearest - baud_rate
+            } else {
+                baud_rate - nearest
+            };
+            if deviation as u64 * 1000 <= baud_rate as u64 * BAUD_RATE_TOLERANCE as u64 {
+                Ok(nearest)
+            } else {
+                Err(ErrorCode::INVAL)
+            }
+        }
+        // The UART cannot tell, so leave the check to `configure`.
+        Err(ErrorCode::NOSUPPORT) => Ok(baud_rate),
+        Err(ecode) => Err(ecode),
+    }
+}
+
+/// Maps a UART receive error to the result and error kind reported in the
+/// read callback. Every kind has its own error code, so apps can tell them
+/// apart from `r0` alone. See the `command` documentation for the mapping.
+fn rx_error_report(error: uart::Error) -> (Result<(), ErrorCode>, usize) {
+    match error {
+        uart::Error::ParityError => (Err(ErrorCode::INVAL), 1),
+        uart::Error::FramingError => (Err(ErrorCode::FAIL), 2),
+        uart::Error::OverrunError => (Err(ErrorCode::SIZE), 3),
+        uart::Error::RepeatCallError => (Err(ErrorCode::BUSY), 4),
+        uart::Error::ResetError => (Err(ErrorCode::OFF), 5),
+        uart::Error::BreakError => (Err(ErrorCode::NOACK), 7),
+        // Not reported as errors, but handled for completeness.
+        uart::Error::Aborted => (Err(ErrorCode::CANCEL), 6),
+        uart::Error::None => (Ok(()), 0),
+    }
+}
+
+/// Maps the outcome of aborting the console's read to the result of the
+/// abort command. `abort` is what `receive_abort` returned, or `None` if no
+/// read was in progress.
+fn rx_abort_result(abort: Option<Result<(), ErrorCode>>) -> Result<(), ErrorCode> {
+    match abort {
+        // Nothing to abort.
+        None | Some(Ok(())) => Err(ErrorCode::ALREADY),
+        // The read was cancelled; its callback reports what had arrived.
+        Some(Err(ErrorCode::BUSY)) => Ok(()),
+        // The read could not be cut short and completes on its own.
+        Some(Err(ecode)) => Err(ecode),
+    }
+}
+
+/// What an app's write does once one of its transactions is handed back.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TxNext {
+    /// Send the next transaction, or finish the write if none is left.
+    Continue,
+    /// Queue the rest of the write behind a higher-priority app.
+    YieldToPriority,
+    /// Let echoed bytes out first, then resume ahead of other apps.
+    YieldToEcho,
+}
+
+/// Decides how a write continues after a transaction, given whether it has
+/// `more` to send, whether it is `atomic`, whether a higher-priority app is
+/// waiting and whether echoed bytes are waiting. Atomic writes never yield.
+fn next_transaction(
+    more: bool,
+    atomic: bool,
+    priority_waiting: bool,
+    echo_waiting: bool,
+) -> TxNext {
+    if !more || atomic {
+        TxNext::Continue
+    } else if priority_waiting {
+        TxNext::YieldToPriority
+    } else if echo_waiting {
+        TxNext::YieldToEcho
+    } else {
+        TxNext::Continue
+    }
+}
+
+/// Yields the IDs produced by `ids` in round-robin order after `last`: the
+/// IDs that follow `last` first, then the ones before it, ending with `last`
+/// itself. If `last` is `None` or no longer present, starts from the top.
+fn round_robin<T, I>(ids: impl Fn() -> I, last: Option<T>) -> impl Iterator<Item = T>
+where
+    T: Copy + PartialEq,
+    I: Iterator<Item = T>,
+{
+    let start = last
+        .and_then(|last| ids().position(|id| id == last))
+        .map_or(0, |pos| pos + 1);
+    ids().skip(start).chain(ids().take(start))
+}
+
+pub struct Console<'a, U: uart::Uart<'a>, A: Alarm<'a>> {
+    uart: &'a U,
+    alarm: &'a A,
+    /// Current configuration of the UART line.
+    parameters: Cell<uart::Parameters>,
+    apps: Grant<App, 8>,
     tx_in_progress: OptionalCell<ProcessId>,
+    /// The app that most recently started a transmission.
+    tx_last_served: OptionalCell<ProcessId>,
+    /// The app whose atomic write is under way, if any. No other app's
+    /// write starts until it is done.
+    tx_held: OptionalCell<ProcessId>,
     tx_buffer: TakeCell<'static, [u8]>,
+    /// Length of `tx_buffer`, kept so it can be reported while the buffer is
+    /// lent to the UART.
+    tx_capacity: usize,
+    /// Rest of a kernel message queued with `print_static` still to be sent.
+    kernel_tx: OptionalCell<&'static [u8]>,
+    /// Whether the transaction in flight carries kernel output.
+    kernel_tx_in_progress: Cell<bool>,
+    /// Rest of an urgent kernel message queued with `print_urgent`.
+    urgent_tx: OptionalCell<&'static [u8]>,
+    /// The app whose write was cut short for an urgent message, to be
+    /// resumed before any other app write.
+    tx_preempted: OptionalCell<ProcessId>,
+    /// The app that has claimed the console for itself, if any. Other apps
+    /// cannot start reads or writes until it releases the claim or exits.
+    exclusive: OptionalCell<ProcessId>,
+    /// Whether the app transaction in flight carries app bytes, rather than
+    /// a CRC trailer, and so can be cut short and resumed exactly.
+    tx_abortable: Cell<bool>,
+    /// Received bytes waiting to be echoed, for apps with echo on.
+    echo_buf: [Cell<u8>; ECHO_BUF_LEN],
+    echo_len: Cell<usize>,
     rx_in_progress: OptionalCell<ProcessId>,
     rx_buffer: TakeCell<'static, [u8]>,
+    /// Length of `rx_buffer`, kept so it can be reported while the buffer is
+    /// lent to the UART.
+    rx_capacity: usize,
+    /// Whether the UART is kept receiving into `rx_ring` between reads, with
+    /// reads served from the ring.
+    rx_continuous: Cell<bool>,
+    /// Whether the UART receive in flight fills `rx_ring` rather than an app
+    /// read.
+    rx_to_ring: Cell<bool>,
+    /// Whether the read in progress is paused: it keeps the line, but the
+    /// UART is not armed for it again until it is resumed.
+    rx_paused: Cell<bool>,
+    rx_ring: RxRing,
+    /// Bytes transmitted and received since boot, across all apps and
+    /// kernel output.
+    tx_bytes: Cell<usize>,
+    rx_bytes: Cell<usize>,
+    /// Received bytes lost since boot because there was nowhere to put them.
+    rx_dropped: Cell<usize>,
+    /// Start and length of the RX idle period currently being timed.
+    rx_idle_timer: OptionalCell<(A::Ticks, A::Ticks)>,
+    /// Start and length of the timeout of the read in progress.
+    rx_timeout_timer: OptionalCell<(A::Ticks, A::Ticks)>,
+    /// Set when the read in progress is being aborted by its timeout.
+    rx_timed_out: Cell<bool>,
+    /// Start and length of the wait before throttled writes are retried.
+    tx_throttle_timer: OptionalCell<(A::Ticks, A::Ticks)>,
+    /// Gap in microseconds the console leaves after every transmission; 0
+    /// sends back to back.
+    tx_gap_us: Cell<u32>,
+    /// Start and length of the gap after the last transmission.
+    tx_gap_timer: OptionalCell<(A::Ticks, A::Ticks)>,
+    /// The app whose write carries on once the gap ends, ahead of other
+    /// apps' queued writes.
+    tx_gap_next: OptionalCell<ProcessId>,
+    /// The app running the loopback self-test, if any. The console does no
+    /// other I/O while it runs.
+    loopback_test: OptionalCell<ProcessId>,
+    /// Whether loopback was on before the self-test turned it on.
+    loopback_restore: Cell<bool>,
+    /// Start and length of the wait for the self-test's bytes to come back.
+    /// It is kept apart from the read timers, which apps can clear.
+    loopback_timer: OptionalCell<(A::Ticks, A::Ticks)>,
+    /// RS-485 driver enable pin, active while transmitting in half-duplex
+    /// mode.
+    rs485_de: OptionalCell<(&'a dyn gpio::Output, gpio::ActivationMode)>,
+    /// RS-485 receiver enable pin, active except while transmitting.
+    rs485_re: OptionalCell<(&'a dyn gpio::Output, gpio::ActivationMode)>,
+    /// Whether half-duplex mode is on.
+    rs485: Cell<bool>,
 }
 
-impl<'a> Console<'a> {
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> Console<'a, U, A> {
     pub fn new(
-        uart: &'a dyn uart::UartData<'a>,
+        uart: &'a U,
+        alarm: &'a A,
+        baud_rate: u32,
         tx_buffer: &'static mut [u8],
         rx_buffer: &'static mut [u8],
-        grant: Grant<App, 3>,
-    ) -> Console<'a> {
+        grant: Grant<App, 8>,
+    ) -> Console<'a, U, A> {
         Console {
             uart: uart,
+            alarm: alarm,
+            parameters: Cell::new(uart::Parameters {
+                baud_rate: baud_rate,
+                width: uart::Width::Eight,
+                parity: uart::Parity::None,
+                stop_bits: uart::StopBits::One,
+                hw_flow_control: false,
+            }),
             apps: grant,
             tx_in_progress: OptionalCell::empty(),
+            tx_last_served: OptionalCell::empty(),
+            tx_held: OptionalCell::empty(),
+            tx_capacity: tx_buffer.len(),
             tx_buffer: TakeCell::new(tx_buffer),
+            kernel_tx: OptionalCell::empty(),
+            kernel_tx_in_progress: Cell::new(false),
+            urgent_tx: OptionalCell::empty(),
+            tx_preempted: OptionalCell::empty(),
+            tx_abortable: Cell::new(false),
+            exclusive: OptionalCell::empty(),
+            echo_buf: Default::default(),
+            echo_len: Cell::new(0),
             rx_in_progress: OptionalCell::empty(),
+            rx_capacity: rx_buffer.len(),
             rx_buffer: TakeCell::new(rx_buffer),
+            rx_continuous: Cell::new(false),
+            rx_to_ring: Cell::new(false),
+            rx_paused: Cell::new(false),
+            rx_ring: RxRing::new(),
+            tx_bytes: Cell::new(0),
+            rx_bytes: Cell::new(0),
+            rx_dropped: Cell::new(0),
+            rx_idle_timer: OptionalCell::empty(),
+            rx_timeout_timer: OptionalCell::empty(),
+            rx_timed_out: Cell::new(false),
+            tx_throttle_timer: OptionalCell::empty(),
+            tx_gap_us: Cell::new(0),
+            tx_gap_timer: OptionalCell::empty(),
+            tx_gap_next: OptionalCell::empty(),
+            loopback_test: OptionalCell::empty(),
+            loopback_restore: Cell::new(false),
+            loopback_timer: OptionalCell::empty(),
+            rs485_de: OptionalCell::empty(),
+            rs485_re: OptionalCell::empty(),
+            rs485: Cell::new(false),
         }
     }
 
-    /// Internal helper function for setting up a new send transaction
-    fn send_new(&self, app_id: ProcessId, app: &mut App, len: usize) -> Result<(), ErrorCode> {
-        app.write_len = cmp::min(len, app.write_buffer.len());
-        app.write_remaining = app.write_len;
-        self.send(app_id, app);
+    /// Provide the pins of an RS-485 transceiver on the console's UART, for
+    /// apps to turn half-duplex mode on with command `36`. The board must
+    /// have configured them as outputs. Transceivers with an active-low
+    /// receiver enable, or with it tied to driver enable, pass that pin with
+    /// `ActiveLow`, or no `re` pin at all.
+    pub fn set_rs485_pins(
+        &self,
+        de: &'a dyn gpio::Output,
+        de_mode: gpio::ActivationMode,
+        re: Option<(&'a dyn gpio::Output, gpio::ActivationMode)>,
+    ) {
+        self.rs485_de.set((de, de_mode));
+        if let Some(re) = re {
+            self.rs485_re.set(re);
+        }
+    }
+
+    /// The capability bitmask returned by command `41`.
+    fn capabilities(&self) -> u32 {
+        let mut caps =
+            CAP_CRC_FRAMING | CAP_CONTINUOUS_RX | CAP_STREAM_READ | CAP_HEX_DUMP | CAP_RX_PAUSE;
+        if self.uart.nearest_baud_rate(self.parameters.get().baud_rate) != Err(ErrorCode::NOSUPPORT)
+        {
+            caps |= CAP_BAUD_QUERY;
+        }
+        if self.rs485_de.is_some() {
+            caps |= CAP_RS485;
+        }
+        caps
+    }
+
+    /// Internal helper function for switching an RS-485 transceiver between
+    /// driving the line (`transmitting`) and listening to it. Does nothing
+    /// unless half-duplex mode is on.
+    fn drive_line(&self, transmitting: bool) {
+        if !self.rs485.get() {
+            return;
+        }
+        let (de_state, re_state) = if transmitting {
+            (
+                gpio::ActivationState::Active,
+                gpio::ActivationState::Inactive,
+            )
+        } else {
+            (
+                gpio::ActivationState::Inactive,
+                gpio::ActivationState::Active,
+            )
+        };
+        self.rs485_de
+            .map(|(pin, mode)| pin.write_activation(de_state, *mode));
+        self.rs485_re
+            .map(|(pin, mode)| pin.write_activation(re_state, *mode));
+    }
+
+    /// Internal helper function for turning RS-485 half-duplex mode on or
+    /// off. Refused with `BUSY` while the console is transmitting.
+    fn set_rs485(&self, enable: bool) -> Result<(), ErrorCode> {
+        if self.rs485_de.is_none() {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        if self.tx_in_progress.is_some() || self.kernel_tx_in_progress.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        if enable {
+            self.rs485.set(true);
+            self.drive_line(false);
+        } else {
+            // Leave the transceiver driving nothing.
+            self.rs485_de
+                .map(|(pin, mode)| pin.write_activation(gpio::ActivationState::Inactive, *mode));
+            self.rs485.set(false);
+        }
         Ok(())
     }
 
+    /// Whether an app currently has a receive outstanding on the console.
+    /// Boards can check this before entering a sleep state that would gate
+    /// the UART clock and lose incoming bytes.
+    pub fn rx_pending(&self) -> bool {
+        self.rx_in_progress.is_some() || self.rx_to_ring.get()
+    }
+
+    /// Print a kernel-owned message, such as a boot banner, through the
+    /// console. The message waits for any app write in flight to finish, but
+    /// goes out ahead of app writes that are still queued.
+    /// Only one message can be queued at a time; returns `BUSY` if the
+    /// previous one has not been fully handed to the UART yet.
+    pub fn print_static(&self, s: &'static [u8]) -> Result<(), ErrorCode> {
+        if self.kernel_tx.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.kernel_tx.set(s);
+        if self.tx_in_progress.is_none() && !self.kernel_tx_in_progress.get() {
+            self.send_kernel();
+        }
+        Ok(())
+    }
+
+    /// Print an urgent kernel message, such as an assertion failure, ahead of
+    /// all other console output. If an app transaction is in flight, it is
+    /// aborted through the UART and the app's write resumes, from the first
+    /// byte the UART did not get out, once the message is sent. The message
+    /// goes out even in the middle of an atomic write. If the UART cannot
+    /// abort, the message waits for the current transaction only. Only one
+    /// urgent message can be queued at a time; returns `BUSY` if the previous
+    /// one has not been fully handed to the UART yet.
+    pub fn print_urgent(&self, s: &'static [u8]) -> Result<(), ErrorCode> {
+        if self.urgent_tx.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.urgent_tx.set(s);
+        if let Some(appid) = self.tx_in_progress.extract() {
+            self.tx_preempted.set(appid);
+            if self.tx_abortable.get() {
+                // The transaction is returned with `CANCEL` or, if the UART
+                // cannot cut it short, once it is done.
+                let _ = self.uart.transmit_abort();
+            }
+        } else if !self.kernel_tx_in_progress.get() {
+            self.send_kernel();
+        }
+        Ok(())
+    }
+
+    /// Internal helper function for queueing received bytes to be echoed.
+    /// Echo is best effort: bytes that do not fit in the echo buffer are not
+    /// echoed.
+    fn echo(&self, data: impl Iterator<Item = u8>) {
+        for byte in data {
+            let len = self.echo_len.get();
+            if len == ECHO_BUF_LEN {
+                break;
+            }
+            self.echo_buf[len].set(byte);
+            self.echo_len.set(len + 1);
+        }
+        if self.tx_in_progress.is_none() && !self.kernel_tx_in_progress.get() {
+            self.send_kernel();
+        }
+    }
+
+    /// Internal helper function for sending kernel output: an urgent message
+    /// first, then echoed bytes, then the next chunk of a queued kernel
+    /// message. Returns `true` if a transaction was started. Output the UART
+    /// rejects is dropped, as there is nobody to report it to.
+    fn send_kernel(&self) -> bool {
+        if self.loopback_test.is_some()
+            || self.tx_gap_timer.is_some()
+            || (self.urgent_tx.is_none() && self.echo_len.get() == 0 && self.kernel_tx.is_none())
+        {
+            return false;
+        }
+        self.tx_buffer.take().map_or(false, |buffer| {
+            let len = if let Some(message) = self.urgent_tx.take() {
+                let len = cmp::min(message.len(), buffer.len());
+                buffer[..len].copy_from_slice(&message[..len]);
+                if len < message.len() {
+                    self.urgent_tx.set(&message[len..]);
+                }
+                len
+            } else if self.echo_len.get() > 0 {
+                let len = cmp::min(self.echo_len.get(), buffer.len());
+                for (dst, src) in buffer.iter_mut().zip(self.echo_buf.iter()).take(len) {
+                    *dst = src.get();
+                }
+                self.echo_len.set(0);
+                len
+            } else {
+                self.kernel_tx.take().map_or(0, |message| {
+                    let len = cmp::min(message.len(), buffer.len());
+                    buffer[..len].copy_from_slice(&message[..len]);
+                    if len < message.len() {
+                        self.kernel_tx.set(&message[len..]);
+                    }
+                    len
+                })
+            };
+            self.kernel_tx_in_progress.set(true);
+            self.drive_line(true);
+            match self.uart.transmit_buffer(buffer, len) {
+                Ok(()) => true,
+                Err((_ecode, buffer)) => {
+                    self.kernel_tx_in_progress.set(false);
+                    self.drive_line(false);
+                    self.urgent_tx.clear();
+                    self.kernel_tx.clear();
+                    self.tx_buffer.replace(buffer);
+                    false
+                }
+            }
+        })
+    }
+
+    /// Internal helper function for setting up a new send transaction of up
+    /// to `len` bytes, taken from the first `segments` allowed write buffers
+    /// back to back. `flags` are those of `arg2` of the putstr command.
+    fn send_new(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        len: usize,
+        segments: usize,
+        flags: usize,
+    ) -> Result<(), ErrorCode> {
+        if app.write_len > 0 || self.claimed_by_other(app_id) {
+            // A write from this app is still in progress, or another app
+            // has the console to itself.
+            return Err(ErrorCode::BUSY);
+        }
+        app.write_segments = segments;
+        let available: usize = app.write_data().map(|buffer| buffer.len()).sum();
+        if available == 0 {
+            // The app never shared a buffer, or the one it shared is empty:
+            // retrying will not help until it calls `allow`.
+            return Err(ErrorCode::INVAL);
+        }
+        app.write_newline = flags & APPEND_NEWLINE != 0;
+        app.write_hex = if flags & HEX_DUMP_SPACED != 0 {
+            Some(3)
+        } else if flags & HEX_DUMP != 0 {
+            Some(2)
+        } else {
+            None
+        };
+        let len = app.output_len(cmp::min(len, available));
+        self.start_write(app_id, app, len, None, flags & ATOMIC_WRITE != 0)
+    }
+
+    /// Internal helper function for setting up a send transaction of `count`
+    /// copies of `byte`, generated in `tx_buffer` rather than read from an
+    /// allowed buffer.
+    fn send_fill(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        byte: u8,
+        count: usize,
+    ) -> Result<(), ErrorCode> {
+        if app.write_len > 0 || self.claimed_by_other(app_id) {
+            return Err(ErrorCode::BUSY);
+        }
+        if count == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        app.write_segments = 0;
+        app.write_newline = false;
+        app.write_hex = None;
+        self.start_write(app_id, app, count, Some(byte), false)
+    }
+
+    /// Internal helper function for starting a write of `len` bytes, taken
+    /// from the app's write buffers, or all equal to `fill` if it is given.
+    fn start_write(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        len: usize,
+        fill: Option<u8>,
+        atomic: bool,
+    ) -> Result<(), ErrorCode> {
+        app.write_len = len;
+        app.write_remaining = app.write_len;
+        app.write_fill = fill;
+        app.cancel_write = false;
+        app.tx_crc = 0;
+        app.tx_crc_pending = app.crc_poly.is_some();
+        app.atomic_write = atomic;
+        self.send(app_id, app).map_err(|ecode| {
+            // The write was rejected before anything was sent, so the app
+            // gets the error here and no callback.
+            app.write_len = 0;
+            app.write_remaining = 0;
+            app.tx_crc_pending = false;
+            ecode
+        })
+    }
+
     /// Internal helper function for continuing a previously set up transaction.
-    /// Returns `true` if this send is still active, or `false` if it has
-    /// completed.
-    fn send_continue(&self, app_id: ProcessId, app: &mut App) -> bool {
-        if app.write_remaining > 0 {
-            self.send(app_id, app);
-            true
+    /// Returns `Ok(true)` if this send is still active, or `Ok(false)` if it
+    /// has completed. A transaction that cannot be continued is completed
+    /// early, with `write_len` trimmed to the bytes actually sent, and the
+    /// error returned so the caller can pass it on in the write callback.
+    fn send_continue(&self, app_id: ProcessId, app: &mut App) -> Result<bool, ErrorCode> {
+        if app.write_remaining > 0 || app.tx_crc_pending {
+            self.send(app_id, app).map(|()| true).map_err(|ecode| {
+                app.truncate_write(0);
+                ecode
+            })
         } else {
-            false
+            Ok(false)
         }
     }
 
-    /// Internal helper function for sending data for an existing transaction.
-    /// Cannot fail. If can't send now, it will schedule for sending later.
-    fn send(&self, app_id: ProcessId, app: &mut App) {
-        if self.tx_in_progress.is_none() {
-            self.tx_in_progress.set(app_id);
-            self.tx_buffer.take().map(|buffer| {
-                let len = app.write_buffer.enter(|data| data.len()).unwrap_or(0);
-                if app.write_remaining > len {
-                    // A slice has changed under us and is now smaller than
-                    // what we need to write -- just write what we can.
-                    app.write_remaining = len;
+    /// Internal helper function for completing an app's write: clears it and
+    /// issues the write callback with the number of bytes written and
+    /// `rcode`. Releases the transmitter if the write held it.
+    fn finish_write(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+        rcode: Result<(), ErrorCode>,
+    ) {
+        if self.tx_held.contains(&app_id) {
+            self.tx_held.clear();
+        }
+        let written = app.write_len;
+        app.write_len = 0;
+        upcalls
+            .schedule_upcall(1, (written, kernel::errorcode::into_statuscode(rcode), 0))
+            .ok();
+    }
+
+    /// Internal helper function for issuing the write progress callback
+    /// after a transaction of a write that has more to send. With a gap the
+    /// write goes out a byte at a time, so progress is reported as often as
+    /// it would be in full chunks.
+    fn report_progress(&self, upcalls: &GrantUpcallTable, written: usize, remaining: usize) {
+        if self.tx_gap_us.get() == 0 || written % cmp::max(self.tx_capacity, 1) == 0 {
+            upcalls.schedule_upcall(4, (written, remaining, 0)).ok();
+        }
+    }
+
+    /// Internal helper function for flushing the calling app's output. The
+    /// flush completes once the app has no write in progress and the UART
+    /// has handed back every transmission the console started, kernel output
+    /// included. If that is already the case, it completes right away.
+    fn flush(&self, app: &mut App, upcalls: &GrantUpcallTable) {
+        app.flush_pending = true;
+        if self.tx_in_progress.is_none() && !self.kernel_tx_in_progress.get() {
+            self.finish_flush(app, upcalls);
+        }
+    }
+
+    /// Internal helper function for issuing the flush callback, if the app
+    /// asked for one and its write is done. Must only be called while the
+    /// UART is idle.
+    fn finish_flush(&self, app: &mut App, upcalls: &GrantUpcallTable) {
+        if app.flush_pending && app.write_len == 0 {
+            app.flush_pending = false;
+            upcalls.schedule_upcall(7, (0, 0, 0)).ok();
+        }
+    }
+
+    /// Internal helper function for noticing that the console has stopped
+    /// transmitting. With nothing left to send, the line is idle: release it
+    /// and complete any flushes waiting on it.
+    fn finish_line(&self) {
+        if self.tx_in_progress.is_none()
+            && !self.kernel_tx_in_progress.get()
+            && self.tx_gap_timer.is_none()
+        {
+            self.drive_line(false);
+            for cntr in self.apps.iter() {
+                cntr.enter(|app, upcalls| self.finish_flush(app, upcalls));
+            }
+        }
+    }
+
+    /// Internal helper function for topping up an app's token bucket. Returns
+    /// how many bytes the app may send right now, or `None` if its output is
+    /// not rate limited. The bucket holds at most one second's worth of
+    /// output.
+    fn tx_quota(&self, app: &mut App) -> Option<usize> {
+        if app.tx_rate_limit == 0 {
+            return None;
+        }
+        // Only the low 32 bits of the refill time are kept. That is exact as
+        // long as refills happen less than one counter period apart, and
+        // the bucket is full long before that at any useful rate.
+        let now = self.alarm.now();
+        let elapsed = now
+            .wrapping_sub(A::Ticks::from(app.tx_refilled_at))
+            .into_u32();
+        let elapsed_ms = self.alarm.ticks_to_ms(A::Ticks::from(elapsed)) as usize;
+        let earned = elapsed_ms.saturating_mul(app.tx_rate_limit) / 1000;
+        if earned > 0 {
+            app.tx_tokens = cmp::min(app.tx_tokens.saturating_add(earned), app.tx_rate_limit);
+            app.tx_refilled_at = now.into_u32();
+        }
+        Some(app.tx_tokens)
+    }
+
+    /// Internal helper function for waiting until a throttled app has earned
+    /// at least one byte. The wait is shared by all throttled apps; when it
+    /// ends every pending write is retried, and those still out of quota
+    /// start a new wait.
+    fn start_tx_throttle_timer(&self, rate: usize) {
+        if self.tx_throttle_timer.is_none() {
+            let ms = cmp::max(1, (1000 + rate - 1) / rate);
+            let dt = self.alarm.ticks_from_ms(ms as u32);
+            self.tx_throttle_timer.set((self.alarm.now(), dt));
+            self.arm_alarm();
+        }
+    }
+
+    /// Internal helper function for starting the gap after a transmission,
+    /// if one is configured. Nothing is transmitted until it ends.
+    fn start_tx_gap_timer(&self) {
+        let gap = self.tx_gap_us.get();
+        if gap > 0 {
+            let dt = self.alarm.ticks_from_us(gap);
+            self.tx_gap_timer.set((self.alarm.now(), dt));
+            self.arm_alarm();
+        }
+    }
+
+    /// Internal helper function for finding the highest transmit priority
+    /// among apps with a queued write, considering only priorities below
+    /// `below` if it is given.
+    fn top_pending_priority(&self, below: Option<usize>) -> Option<usize> {
+        self.apps
+            .iter()
+            .filter_map(|cntr| {
+                cntr.enter(|app, _| {
+                    if app.pending_write && below.map_or(true, |below| app.tx_priority < below) {
+                        Some(app.tx_priority)
+                    } else {
+                        None
+                    }
+                })
+            })
+            .max()
+    }
+
+    /// Internal helper function for starting the next queued app write, if
+    /// any. Must only be called while the UART is idle. Apps with the highest
+    /// transmit priority are served first. Among apps of equal priority, the
+    /// scan is round-robin, starting after the app that last started a write,
+    /// so that none of them can starve the others. Lower priorities are only
+    /// tried if no write at a higher one could start, e.g. because it is
+    /// throttled. While an atomic write holds the transmitter, only its app
+    /// is served.
+    fn send_pending(&self) {
+        if self.tx_gap_timer.is_some() {
+            // Nothing can start until the gap ends, which calls this again.
+            return;
+        }
+        if let Some(preempted) = self.tx_preempted.take() {
+            let _ = self.apps.enter(preempted, |app, upcalls| {
+                self.send_queued(preempted, app, upcalls);
+            });
+            if self.tx_in_progress.is_some() {
+                return;
+            }
+        }
+        if let Some(holder) = self.tx_held.extract() {
+            let entered = self.apps.enter(holder, |app, upcalls| {
+                self.send_queued(holder, app, upcalls);
+            });
+            if entered.is_ok() {
+                return;
+            }
+            // The app went away partway through its write.
+            self.tx_held.clear();
+        }
+        let mut below = None;
+        while let Some(priority) = self.top_pending_priority(below) {
+            let order = round_robin(
+                || self.apps.iter().map(|cntr| cntr.processid()),
+                self.tx_last_served.extract(),
+            );
+            for appid in order {
+                let _ = self.apps.enter(appid, |app, upcalls| {
+                    if app.tx_priority == priority {
+                        self.send_queued(appid, app, upcalls);
+                    }
+                });
+                if self.tx_in_progress.is_some() {
+                    return;
                 }
-                let transaction_len = app
-                    .write_buffer
-                    .enter(|data| {
-                        for (i, c) in data[data.len() - app.write_remaining..data.len()]
-                            .iter()
-                            .enumerate()
-                        {
-                            if buffer.len() <= i {
-                                return i; // Short circuit on partial send
+            }
+            below = Some(priority);
+        }
+    }
+
+    /// Internal helper function for starting an app's queued write, if it has
+    /// one.
+    fn send_queued(&self, app_id: ProcessId, app: &mut App, upcalls: &GrantUpcallTable) {
+        if app.pending_write {
+            app.pending_write = false;
+            match self.send_continue(app_id, app) {
+                Ok(true) => {}
+                // The queued write could not start; it still owes the app
+                // its callback.
+                Ok(false) => self.finish_write(app_id, app, upcalls, Ok(())),
+                Err(ecode) => self.finish_write(app_id, app, upcalls, Err(ecode)),
+            }
+        }
+    }
+
+    /// Internal helper function for sending data for an existing transaction.
+    /// If the UART is in use, or the app has used up its output quota, it
+    /// will schedule for sending later. Fails only if the UART rejects the
+    /// transaction outright, in which case nothing from this transaction was
+    /// sent.
+    fn send(&self, app_id: ProcessId, app: &mut App) -> Result<(), ErrorCode> {
+        let held_by_other = self.tx_held.map_or(false, |holder| *holder != app_id);
+        if self.tx_in_progress.is_none()
+            && !self.kernel_tx_in_progress.get()
+            && !held_by_other
+            && self.loopback_test.is_none()
+            && self.tx_gap_timer.is_none()
+        {
+            let quota = self.tx_quota(app);
+            if quota == Some(0) {
+                app.pending_write = true;
+                self.start_tx_throttle_timer(app.tx_rate_limit);
+                return Ok(());
+            }
+            self.tx_buffer
+                .take()
+                .map_or(Err(ErrorCode::FAIL), |buffer| {
+                    let max_len = if self.tx_gap_us.get() > 0 {
+                        // Every byte is followed by the gap.
+                        1
+                    } else {
+                        quota.map_or(buffer.len(), |q| cmp::min(q, buffer.len()))
+                    };
+                    let sent = app.write_len - app.write_remaining;
+                    let available =
+                        app.output_len(app.write_data().map(|buffer| buffer.len()).sum());
+                    // A slice may have changed under us and now be smaller
+                    // than what we need to write -- just write what we can.
+                    app.clamp_write(available);
+                    if app.write_remaining == 0 && !app.tx_crc_pending {
+                        // Nothing of the write is left to send.
+                        self.tx_buffer.replace(buffer);
+                        return Err(ErrorCode::SIZE);
+                    }
+                    // Once the app's bytes are all out, a framed write sends
+                    // its CRC trailer as a transaction of its own.
+                    let trailer = app.write_remaining == 0;
+                    let (transaction_len, payload_len, crc) = if trailer {
+                        let crc = app.tx_crc.to_be_bytes();
+                        let len = cmp::min(CRC_LEN, buffer.len());
+                        buffer[..len].copy_from_slice(&crc[..len]);
+                        (len, 0, app.tx_crc)
+                    } else {
+                        // Build the next bytes of the write, which may span
+                        // several allowed buffers, stopping short on a
+                        // partial send.
+                        let end = sent + cmp::min(app.write_remaining, max_len);
+                        let len =
+                            build_chunk(buffer, app.write_data(), app.write_format(), sent, end);
+                        let crc = app.crc_poly.map_or(app.tx_crc, |poly| {
+                            crc16(poly, app.tx_crc, buffer[..len].iter().copied())
+                        });
+                        (len, len, crc)
+                    };
+                    let tokens = app.begin_transaction(transaction_len, payload_len, crc, trailer);
+                    self.tx_in_progress.set(app_id);
+                    self.tx_last_served.set(app_id);
+                    self.tx_abortable.set(!trailer);
+                    self.drive_line(true);
+                    self.uart
+                        .transmit_buffer(buffer, transaction_len)
+                        .map(|()| {
+                            if app.atomic_write {
+                                self.tx_held.set(app_id);
                             }
-                            buffer[i] = c.get();
-                        }
-                        app.write_remaining
-                    })
-                    .unwrap_or(0);
-                app.write_remaining -= transaction_len;
-                let _ = self.uart.transmit_buffer(buffer, transaction_len);
-            });
+                        })
+                        .map_err(|(ecode, buffer)| {
+                            // The UART rejected the transaction: it goes out
+                            // again next time.
+                            app.unwind_transaction(trailer, tokens);
+                            self.tx_in_progress.clear();
+                            self.drive_line(false);
+                            self.tx_buffer.replace(buffer);
+                            ecode
+                        })
+                })
         } else {
             app.pending_write = true;
+            Ok(())
+        }
+    }
+
+    /// Internal helper function for cancelling an app's write. A write that is
+    /// still queued behind another app is dropped on the spot. An in-flight
+    /// write is aborted through the UART; if the UART cannot cancel it
+    /// synchronously, the current transaction is left to drain and the rest
+    /// of the write is dropped once it completes. In every case the write
+    /// upcall reports how many bytes were sent. Returns `1` if cancellation
+    /// was deferred until the current transaction drains, `0` otherwise.
+    fn abort_write(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+    ) -> CommandReturn {
+        if app.write_len == 0 {
+            // Nothing to cancel.
+            CommandReturn::success_u32(0)
+        } else if self.tx_in_progress.contains(&app_id) {
+            app.cancel_write = true;
+            match self.uart.transmit_abort() {
+                // The UART cancelled the transaction and will return the
+                // buffer with `CANCEL`.
+                Err(ErrorCode::BUSY) => CommandReturn::success_u32(0),
+                // The UART cannot cut the transaction short (e.g. `FAIL` or
+                // `NOSUPPORT`), so wait for it to drain.
+                _ => CommandReturn::success_u32(1),
+            }
+        } else {
+            app.pending_write = false;
+            app.truncate_write(0);
+            self.finish_write(app_id, app, upcalls, Ok(()));
+            CommandReturn::success_u32(0)
+        }
+    }
+
+    /// Internal helper function for dropping an app's queued write before any
+    /// of it is sent. Unlike `abort_write`, this issues no callback.
+    fn drop_pending_write(&self, app: &mut App) -> Result<(), ErrorCode> {
+        if !app.pending_write {
+            Ok(())
+        } else if app.write_remaining < app.write_len {
+            Err(ErrorCode::BUSY)
+        } else {
+            app.pending_write = false;
+            app.write_len = 0;
+            app.write_remaining = 0;
+            app.tx_crc_pending = false;
+            Ok(())
+        }
+    }
+
+    /// Internal helper function for reconfiguring the UART line. As this
+    /// affects every user of the line, it is refused with `BUSY` while any
+    /// transmission or reception is in progress, including continuous
+    /// receive.
+    fn reconfigure(&self, parameters: uart::Parameters) -> CommandReturn {
+        if self.tx_in_progress.is_some()
+            || self.kernel_tx_in_progress.get()
+            || self.rx_in_progress.is_some()
+            || self.rx_to_ring.get()
+            || self.loopback_test.is_some()
+        {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        let result = self.uart.configure(parameters);
+        if result.is_ok() {
+            self.parameters.set(parameters);
+        }
+        result.into()
+    }
+
+    /// Internal helper function for setting the parity and stop bits of the
+    /// UART line, encoded as in the `command` documentation.
+    fn set_framing(&self, parity: usize, stop_bits: usize) -> CommandReturn {
+        let parity = match parity {
+            0 => uart::Parity::None,
+            1 => uart::Parity::Odd,
+            2 => uart::Parity::Even,
+            _ => return CommandReturn::failure(ErrorCode::INVAL),
+        };
+        let stop_bits = match stop_bits {
+            1 => uart::StopBits::One,
+            2 => uart::StopBits::Two,
+            _ => return CommandReturn::failure(ErrorCode::INVAL),
+        };
+        self.reconfigure(uart::Parameters {
+            parity: parity,
+            stop_bits: stop_bits,
+            ..self.parameters.get()
+        })
+    }
+
+    /// Internal helper function for checking whether the UART can generate
+    /// `baud_rate` closely enough for a peer to receive it reliably.
+    fn check_baud_rate(&self, baud_rate: u32) -> CommandReturn {
+        match self.uart.nearest_baud_rate(baud_rate) {
+            Ok(nearest) => match baud_rate_result(baud_rate, Ok(nearest)) {
+                Ok(nearest) => CommandReturn::success_u32(nearest),
+                Err(ecode) => CommandReturn::failure_u32(ecode, nearest),
+            },
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    /// Internal helper function for changing the baud rate of the UART line.
+    /// Rejects rates the UART cannot generate before touching the hardware.
+    fn set_baud_rate(&self, baud_rate: u32) -> CommandReturn {
+        if let Err(ecode) = baud_rate_result(baud_rate, self.uart.nearest_baud_rate(baud_rate)) {
+            return CommandReturn::failure(ecode);
+        }
+        self.reconfigure(uart::Parameters {
+            baud_rate,
+            ..self.parameters.get()
+        })
+    }
+
+    /// Internal helper function for starting a receive operation
+    fn receive_new(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+        len: usize,
+        delimiter: Option<u8>,
+    ) -> Result<(), ErrorCode> {
+        if self.rx_in_progress.is_some() || !self.rx_ready() || self.claimed_by_other(app_id) {
+            // For now, we tolerate only one concurrent receive operation on this console.
+            // Competing apps will have to retry until success.
+            return Err(ErrorCode::BUSY);
+        }
+        if app.read_buffer.len() == 0 {
+            // As for writes, there is nothing to receive into until the app
+            // calls `allow`.
+            return Err(ErrorCode::INVAL);
+        }
+
+        // A framed read also receives the CRC trailer after `len` bytes of
+        // payload.
+        let wanted = if app.crc_poly.is_some() {
+            len.saturating_add(CRC_LEN)
+        } else {
+            len
+        };
+        // Reads longer than `rx_buffer` are received in chunks of at most
+        // its length; see `receive_next`.
+        app.read_len = cmp::min(wanted, app.read_buffer.len());
+        app.read_offset = 0;
+        app.read_delimiter = delimiter;
+        app.read_byte = false;
+        app.read_stream = false;
+        self.receive_start(app_id, app, upcalls);
+        Ok(())
+    }
+
+    /// Internal helper function for receiving a single byte that is handed
+    /// to the app in the read callback, so no read buffer is needed.
+    fn receive_byte(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+    ) -> Result<(), ErrorCode> {
+        if self.rx_in_progress.is_some() || !self.rx_ready() || self.claimed_by_other(app_id) {
+            return Err(ErrorCode::BUSY);
+        }
+        app.read_len = 1;
+        app.read_offset = 0;
+        app.read_delimiter = None;
+        app.read_byte = true;
+        app.read_stream = false;
+        self.receive_start(app_id, app, upcalls);
+        Ok(())
+    }
+
+    /// Internal helper function for starting a streaming read, which hands
+    /// received bytes to the app as they arrive, through its read buffer,
+    /// until the app aborts it.
+    fn receive_stream(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+    ) -> Result<(), ErrorCode> {
+        if self.rx_in_progress.is_some() || !self.rx_ready() || self.claimed_by_other(app_id) {
+            return Err(ErrorCode::BUSY);
+        }
+        if app.read_buffer.len() == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        app.read_len = 0;
+        app.read_offset = 0;
+        app.read_delimiter = None;
+        app.read_byte = false;
+        app.read_stream = true;
+        self.receive_start(app_id, app, upcalls);
+        Ok(())
+    }
+
+    /// Internal helper function for handing bytes received for a streaming
+    /// read to the app. They are stored in its read buffer from
+    /// `read_offset` on, wrapping around at the end of the buffer, and each
+    /// run of bytes stored back to back is reported in a read callback with
+    /// its length in `r1` and its offset in `r2`.
+    fn stream_chunk(
+        &self,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+        data: impl Iterator<Item = u8>,
+    ) {
+        let report = |start: usize, len: usize| {
+            if len > 0 {
+                upcalls
+                    .schedule_upcall(2, (kernel::errorcode::into_statuscode(Ok(())), len, start))
+                    .ok();
+            }
+        };
+        let mut start = app.read_offset;
+        let mut len = 0;
+        for byte in data {
+            if app.read_offset >= app.read_buffer.len() {
+                report(start, len);
+                start = 0;
+                len = 0;
+                app.read_offset = 0;
+            }
+            let offset = app.read_offset;
+            let stored = app
+                .read_buffer
+                .mut_enter(|buffer| {
+                    if offset < buffer.len() {
+                        buffer[offset].set(byte);
+                        true
+                    } else {
+                        false
+                    }
+                })
+                .unwrap_or(false);
+            if !stored {
+                // The app revoked its buffer under the read.
+                self.count_rx_dropped(1);
+                continue;
+            }
+            if app.echo {
+                self.echo(core::iter::once(byte));
+            }
+            app.read_offset += 1;
+            len += 1;
+        }
+        report(start, len);
+    }
+
+    /// Whether an app other than `app_id` has claimed exclusive access. A
+    /// claim held by an app that no longer exists is dropped here.
+    fn claimed_by_other(&self, app_id: ProcessId) -> bool {
+        self.exclusive.extract().map_or(false, |owner| {
+            if owner == app_id {
+                false
+            } else if self.apps.enter(owner, |_, _| {}).is_ok() {
+                true
+            } else {
+                self.exclusive.clear();
+                false
+            }
+        })
+    }
+
+    /// Internal helper function for claiming (`claim` set) or releasing
+    /// exclusive access to the console for `app_id`.
+    fn set_exclusive(&self, app_id: ProcessId, claim: bool) -> Result<(), ErrorCode> {
+        if self.claimed_by_other(app_id) {
+            Err(if claim {
+                ErrorCode::BUSY
+            } else {
+                ErrorCode::RESERVE
+            })
+        } else {
+            if claim {
+                self.exclusive.set(app_id);
+            } else {
+                self.exclusive.clear();
+            }
+            Ok(())
+        }
+    }
+
+    /// Whether a new read can start: either it is served from the ring, or
+    /// `rx_buffer` is free to arm the UART with.
+    fn rx_ready(&self) -> bool {
+        self.loopback_test.is_none() && (self.rx_continuous.get() || self.rx_buffer.is_some())
+    }
+
+    /// Internal helper function for starting a read that has been set up in
+    /// `app`.
+    fn receive_start(&self, app_id: ProcessId, app: &mut App, upcalls: &GrantUpcallTable) {
+        self.rx_paused.set(false);
+        if self.rx_continuous.get() {
+            if let Some(error) = self.rx_ring.take_error() {
+                // The UART reported an error since the last read ended.
+                self.receive_error(app, upcalls, error);
+                return;
+            }
+            self.rx_in_progress.set(app_id);
+            self.drain_ring(app, upcalls);
+        } else {
+            self.receive_next(app_id, app, upcalls);
         }
     }
 
-    /// Internal helper function for starting a receive operation
-    fn receive_new(&self, app_id: ProcessId, app: &mut App, len: usize) -> Result<(), ErrorCode> {
-        if self.rx_buffer.is_none() {
-            // For now, we tolerate only one concurrent receive operation on this console.
-            // Competing apps will have to retry until success.
+    /// Internal helper function for turning continuous receive on or off. It
+    /// changes how every read is served, so it is refused with `BUSY` while
+    /// a read is in progress.
+    fn set_continuous_rx(&self, enable: bool) -> CommandReturn {
+        if self.rx_in_progress.is_some() || self.loopback_test.is_some() {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        self.rx_continuous.set(enable);
+        if enable && !self.rx_to_ring.get() {
+            self.receive_to_ring();
+        } else if !enable && self.rx_to_ring.get() {
+            // The ring stops being refilled once the UART returns the buffer.
+            let _ = self.uart.receive_abort();
+        }
+        CommandReturn::success()
+    }
+
+    /// Internal helper function for arming the UART to receive the next byte
+    /// into the ring.
+    fn receive_to_ring(&self) {
+        self.rx_buffer.take().map(|buffer| {
+            self.rx_to_ring.set(true);
+            if let Err((_, buffer)) = self.uart.receive_buffer(buffer, 1) {
+                self.rx_to_ring.set(false);
+                self.rx_buffer.replace(buffer);
+            }
+        });
+    }
+
+    /// Internal helper function for copying up to `len` bytes waiting in the
+    /// ring into the app's read buffer right away, without starting a read.
+    /// Returns how many bytes were copied.
+    fn read_available(&self, app_id: ProcessId, app: &mut App, len: usize) -> CommandReturn {
+        if self.rx_in_progress.is_some() || self.claimed_by_other(app_id) {
+            // The bytes in the ring belong to the read in progress.
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        if app.read_buffer.len() == 0 {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+        if let Some(error) = self.rx_ring.take_error() {
+            // There is no callback to report it in: keep it for `38`.
+            app.rx_last_error = Some(error);
+        }
+        let ring = &self.rx_ring;
+        let count = app
+            .read_buffer
+            .mut_enter(|data| {
+                let mut count = 0;
+                for slot in data.iter().take(len) {
+                    match ring.pop() {
+                        Some(byte) => slot.set(byte),
+                        None => break,
+                    }
+                    count += 1;
+                }
+                count
+            })
+            .unwrap_or(0);
+        if app.echo && count > 0 {
+            let _ = app.read_buffer.enter(|data| {
+                self.echo(data.iter().take(count).map(|b| b.get()));
+            });
+        }
+        CommandReturn::success_u32(count as u32)
+    }
+
+    /// Internal helper function for moving bytes waiting in the ring into the
+    /// read in progress, completing the read once it has all it asked for or
+    /// its delimiter.
+    fn drain_ring(&self, app: &mut App, upcalls: &GrantUpcallTable) {
+        if self.rx_paused.get() {
+            // The bytes wait in the ring until the read is resumed.
+            return;
+        }
+        if app.read_stream {
+            let ring = &self.rx_ring;
+            self.stream_chunk(app, upcalls, core::iter::from_fn(|| ring.pop()));
+            return;
+        }
+        if app.read_byte {
+            if let Some(byte) = self.rx_ring.pop() {
+                if app.echo {
+                    self.echo(core::iter::once(byte));
+                }
+                self.finish_ring_read(app, upcalls, Ok(()), Some(byte));
+            }
+            return;
+        }
+        let start = app.read_offset;
+        let mut delimited = false;
+        while app.read_offset < app.read_len && !delimited {
+            let byte = match self.rx_ring.pop() {
+                Some(byte) => byte,
+                None => break,
+            };
+            let offset = app.read_offset;
+            let stored = app
+                .read_buffer
+                .mut_enter(|data| {
+                    if offset < data.len() {
+                        data[offset].set(byte);
+                        true
+                    } else {
+                        false
+                    }
+                })
+                .unwrap_or(false);
+            if !stored {
+                // The app shrank or revoked its buffer under the read.
+                self.rx_ring.count_dropped();
+                self.count_rx_dropped(1);
+                self.finish_ring_read(app, upcalls, Err(ErrorCode::SIZE), None);
+                return;
+            }
+            if app.echo {
+                self.echo(core::iter::once(byte));
+            }
+            app.read_offset += 1;
+            delimited = app.read_delimiter == Some(byte);
+        }
+        if app.read_offset == app.read_len || delimited {
+            self.finish_ring_read(app, upcalls, Ok(()), None);
+        } else if app.read_offset > start && app.detects_idle() {
+            self.start_rx_idle_timer(app);
+        }
+    }
+
+    /// Internal helper function for completing a read served from the ring
+    /// with `rcode`. A single byte read carries `byte` in the callback, and
+    /// is cancelled if there is none. Bytes the ring dropped since the last
+    /// read are reported in `r2`.
+    fn finish_ring_read(
+        &self,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+        rcode: Result<(), ErrorCode>,
+        byte: Option<u8>,
+    ) {
+        self.rx_in_progress.clear();
+        let timed_out = self.stop_rx_timers();
+        let dropped = self.rx_ring.take_dropped();
+        if app.read_stream {
+            // A stream only ends when it is aborted.
+            app.read_stream = false;
+            let ret = rcode.and(Err(ErrorCode::CANCEL));
+            upcalls
+                .schedule_upcall(2, (kernel::errorcode::into_statuscode(ret), 0, dropped))
+                .ok();
+            return;
+        }
+        if app.read_byte {
+            app.read_byte = false;
+            let ret = byte.map_or(rcode.and(Err(ErrorCode::CANCEL)), |_| rcode);
+            upcalls
+                .schedule_upcall(
+                    2,
+                    (
+                        kernel::errorcode::into_statuscode(ret),
+                        byte.unwrap_or(0) as usize,
+                        dropped,
+                    ),
+                )
+                .ok();
+            return;
+        }
+        let (ret, mut received_length) = if app.read_offset > app.read_buffer.len() {
+            // The app shrank its buffer under the read: report what is
+            // still in it, as `received_buffer` does.
+            (Err(ErrorCode::SIZE), app.read_buffer.len())
+        } else if timed_out {
+            (Ok(()), app.read_offset)
+        } else {
+            (rcode, app.read_offset)
+        };
+        if let (Some(poly), Ok(())) = (app.crc_poly, ret) {
+            let (payload_len, matched) = self.check_rx_crc(app, poly, received_length);
+            received_length = payload_len;
+            if !matched {
+                upcalls
+                    .schedule_upcall(3, (LineEvent::CrcMismatch as usize, payload_len, 0))
+                    .ok();
+            }
+        }
+        upcalls
+            .schedule_upcall(
+                2,
+                (
+                    kernel::errorcode::into_statuscode(ret),
+                    received_length,
+                    dropped,
+                ),
+            )
+            .ok();
+    }
+
+    /// Internal helper function for starting the loopback self-test: with
+    /// the UART looped back, send `LOOPBACK_PATTERN` and receive it again.
+    /// The result is reported through the self-test callback. Refused with
+    /// `BUSY` while any other transmission or reception is in progress.
+    fn start_loopback_test(&self, app_id: ProcessId) -> Result<(), ErrorCode> {
+        if self.tx_in_progress.is_some()
+            || self.kernel_tx_in_progress.get()
+            || self.rx_in_progress.is_some()
+            || self.rx_to_ring.get()
+            || self.loopback_test.is_some()
+            || self.tx_buffer.is_none()
+            || self.rx_buffer.is_none()
+        {
+            return Err(ErrorCode::BUSY);
+        }
+        let previous = self.uart.set_loopback(true)?;
+        self.loopback_test.set(app_id);
+        self.loopback_restore.set(previous);
+
+        let rx_buffer = self.rx_buffer.take().unwrap_or_default();
+        let len = cmp::min(LOOPBACK_PATTERN.len(), rx_buffer.len());
+        if let Err((ecode, buffer)) = self.uart.receive_buffer(rx_buffer, len) {
+            self.rx_buffer.replace(buffer);
+            self.loopback_test.clear();
+            let _ = self.uart.set_loopback(previous);
+            return Err(ecode);
+        }
+        let tx_buffer = self.tx_buffer.take().unwrap_or_default();
+        let len = cmp::min(len, tx_buffer.len());
+        tx_buffer[..len].copy_from_slice(&LOOPBACK_PATTERN[..len]);
+        if let Err((ecode, buffer)) = self.uart.transmit_buffer(tx_buffer, len) {
+            // The receive comes back aborted; with the test cleared it just
+            // returns the buffer.
+            self.tx_buffer.replace(buffer);
+            self.loopback_test.clear();
+            let _ = self.uart.receive_abort();
+            let _ = self.uart.set_loopback(previous);
+            return Err(ecode);
+        }
+        let dt = self.alarm.ticks_from_ms(LOOPBACK_TIMEOUT_MS);
+        self.loopback_timer.set((self.alarm.now(), dt));
+        self.arm_alarm();
+        Ok(())
+    }
+
+    /// Internal helper function for completing the loopback self-test with
+    /// the bytes received back. Restores the loopback state and resumes the
+    /// output held back during the test.
+    fn finish_loopback_test(&self, app_id: ProcessId, received: &[u8], error: uart::Error) {
+        self.loopback_timer.clear();
+        self.arm_alarm();
+        let _ = self.uart.set_loopback(self.loopback_restore.get());
+        let matched = received
+            .iter()
+            .zip(LOOPBACK_PATTERN.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let ret = match error {
+            uart::Error::None if matched == LOOPBACK_PATTERN.len() => Ok(()),
+            uart::Error::None | uart::Error::Aborted => Err(ErrorCode::FAIL),
+            _ => rx_error_report(error).0,
+        };
+        let _ = self.apps.enter(app_id, |_, upcalls| {
+            upcalls
+                .schedule_upcall(6, (kernel::errorcode::into_statuscode(ret), matched, 0))
+                .ok();
+        });
+        // If the test's transmission has not been returned yet, this happens
+        // once it is.
+        if self.tx_buffer.is_some() && !self.send_kernel() {
+            self.send_pending();
+        }
+    }
+
+    /// Internal helper function for counting received bytes that were lost.
+    fn count_rx_dropped(&self, count: usize) {
+        self.rx_dropped
+            .set(self.rx_dropped.get().wrapping_add(count));
+    }
+
+    /// Internal helper function for ending a read because the UART reported
+    /// `error`.
+    fn receive_error(&self, app: &mut App, upcalls: &GrantUpcallTable, error: uart::Error) {
+        app.read_byte = false;
+        app.read_stream = false;
+        app.rx_last_error = Some(error);
+        self.stop_rx_timers();
+        let (ret, kind) = rx_error_report(error);
+        if error == uart::Error::BreakError {
+            upcalls.schedule_upcall(5, (app.read_offset, 0, 0)).ok();
+        }
+        upcalls
+            .schedule_upcall(2, (kernel::errorcode::into_statuscode(ret), 0, kind))
+            .ok();
+    }
+
+    /// Internal helper function for checking and stripping the CRC trailer of
+    /// a framed read of `len` bytes. Returns the payload length and whether
+    /// the CRC matched.
+    fn check_rx_crc(&self, app: &App, poly: u16, len: usize) -> (usize, bool) {
+        if len < CRC_LEN {
+            return (len, false);
+        }
+        let payload_len = len - CRC_LEN;
+        let matched = app
+            .read_buffer
+            .enter(|data| {
+                // The buffer may have shrunk since the bytes were stored: a
+                // trailer that is no longer there does not match.
+                let crc = crc16(poly, 0, data.iter().take(payload_len).map(|b| b.get()));
+                let mut trailer = data.iter().skip(payload_len).map(|b| b.get());
+                match (trailer.next(), trailer.next()) {
+                    (Some(hi), Some(lo)) => crc == u16::from_be_bytes([hi, lo]),
+                    _ => false,
+                }
+            })
+            .unwrap_or(false);
+        (payload_len, matched)
+    }
+
+    /// Report the capacity of the kernel receive buffer and how many bytes of
+    /// the read in progress, if any, have been received so far. The capacity
+    /// is fixed when the board is built; it is the largest chunk a read is
+    /// received in. With continuous receive on, the ring is where received
+    /// bytes are held, so its capacity and fill are reported instead.
+    fn rx_buffer_status(&self, app_id: ProcessId, app: &App) -> CommandReturn {
+        if self.rx_continuous.get() {
+            return CommandReturn::success_u32_u32(
+                RX_RING_LEN as u32,
+                self.rx_ring.len.get() as u32,
+            );
+        }
+        let fill = self.rx_in_progress.map_or(0, |reader| {
+            if *reader == app_id {
+                // The caller's grant is already entered.
+                app.read_offset
+            } else {
+                self.apps
+                    .enter(*reader, |reader_app, _| reader_app.read_offset)
+                    .unwrap_or(0)
+            }
+        });
+        CommandReturn::success_u32_u32(self.rx_capacity as u32, fill as u32)
+    }
+
+    /// Internal helper function for counting the apps that have shared a
+    /// write or read buffer with the console, the caller included.
+    fn active_apps(&self, app_id: ProcessId, app: &App) -> usize {
+        let has_buffers = |app: &App| app.write_buffer.len() > 0 || app.read_buffer.len() > 0;
+        let others = self
+            .apps
+            .iter()
+            // The caller's grant is already entered.
+            .filter(|cntr| cntr.processid() != app_id)
+            .map(|cntr| cntr.enter(|other, _| has_buffers(other) as usize))
+            .sum::<usize>();
+        others + has_buffers(app) as usize
+    }
+
+    /// Internal helper function for pausing or resuming the app's read in
+    /// progress. A paused read keeps what it has received and its place on
+    /// the line. Returns `ALREADY` if the app has no read in progress or it
+    /// is already in the requested state.
+    fn pause_read(
+        &self,
+        app_id: ProcessId,
+        app: &mut App,
+        upcalls: &GrantUpcallTable,
+        pause: bool,
+    ) -> Result<(), ErrorCode> {
+        if !self.rx_in_progress.contains(&app_id) || self.rx_paused.get() == pause {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.rx_paused.set(pause);
+        if pause {
+            // Nothing arrives for the read while it is paused.
+            self.rx_idle_timer.clear();
+            self.arm_alarm();
+        } else if self.rx_continuous.get() {
+            self.drain_ring(app, upcalls);
+        } else if self.rx_buffer.is_some() {
+            // The read stopped at a chunk boundary: arm the UART for the
+            // rest of it. Otherwise its chunk is still in flight and the
+            // read carries on once it completes.
+            self.receive_next(app_id, app, upcalls);
+        }
+        Ok(())
+    }
+
+    /// Internal helper function for discarding received bytes no read has
+    /// taken yet, for a fresh start before the next read. Refused with
+    /// `BUSY` while a read is in progress.
+    fn flush_rx(&self) -> Result<(), ErrorCode> {
+        if self.rx_in_progress.is_some() || self.loopback_test.is_some() {
             return Err(ErrorCode::BUSY);
         }
+        if self.rx_continuous.get() {
+            // The UART keeps receiving into the ring, so its stale bytes
+            // are all in the ring.
+            self.rx_ring.clear();
+            Ok(())
+        } else {
+            self.uart.receive_flush()
+        }
+    }
 
-        let read_len = cmp::min(len, app.read_buffer.len());
-        if read_len > self.rx_buffer.map_or(0, |buf| buf.len()) {
-            // For simplicity, impose a small maximum receive length
-            // instead of doing incremental reads
-            Err(ErrorCode::INVAL)
+    /// Internal helper function for arming the UART for the rest of an active
+    /// read, or as much of it as fits in `rx_buffer`. With RX idle detection or a delimiter the read is received one
+    /// byte at a time, so that every arrival restarts the idle timer and the
+    /// read ends right at the delimiter. If the UART refuses to receive, the
+    /// read ends with its error and the bytes received so far.
+    fn receive_next(&self, app_id: ProcessId, app: &mut App, upcalls: &GrantUpcallTable) {
+        let remaining = app.read_len.saturating_sub(app.read_offset);
+        let len = if app.read_stream {
+            1
+        } else if app.detects_idle() || app.read_delimiter.is_some() {
+            cmp::min(remaining, 1)
         } else {
-            // Note: We have ensured above that rx_buffer is present
-            app.read_len = read_len;
-            self.rx_buffer.take().map(|buffer| {
-                self.rx_in_progress.set(app_id);
-                let _ = self.uart.receive_buffer(buffer, app.read_len);
-            });
-            Ok(())
+            remaining
+        };
+        self.rx_buffer.take().map(|buffer| {
+            let len = cmp::min(len, buffer.len());
+            self.rx_in_progress.set(app_id);
+            if let Err((ecode, buffer)) = self.uart.receive_buffer(buffer, len) {
+                self.rx_buffer.replace(buffer);
+                self.rx_in_progress.clear();
+                self.stop_rx_timers();
+                app.read_byte = false;
+                app.read_stream = false;
+                upcalls
+                    .schedule_upcall(
+                        2,
+                        (
+                            kernel::errorcode::into_statuscode(Err(ecode)),
+                            app.read_offset,
+                            0,
+                        ),
+                    )
+                    .ok();
+            }
+        });
+    }
+
+    /// Internal helper function for (re)starting the RX idle timer for the
+    /// app's configured idle period. A period in character-times follows the
+    /// current line configuration.
+    fn start_rx_idle_timer(&self, app: &App) {
+        let dt = if app.rx_idle_chars > 0 {
+            let us = char_time_us(self.parameters.get()).saturating_mul(app.rx_idle_chars as u32);
+            self.alarm.ticks_from_us(us)
+        } else {
+            self.alarm.ticks_from_ms(app.rx_idle_ms as u32)
+        };
+        self.rx_idle_timer.set((self.alarm.now(), dt));
+        self.arm_alarm();
+    }
+
+    /// Internal helper function for starting the timeout of a read.
+    fn start_rx_timeout_timer(&self, timeout_ms: usize) {
+        let dt = self.alarm.ticks_from_ms(timeout_ms as u32);
+        self.rx_timed_out.set(false);
+        self.rx_timeout_timer.set((self.alarm.now(), dt));
+        self.arm_alarm();
+    }
+
+    /// Internal helper function for stopping the timers of a read that has
+    /// ended. Returns whether the read was ended by its timeout.
+    fn stop_rx_timers(&self) -> bool {
+        self.rx_idle_timer.clear();
+        self.rx_timeout_timer.clear();
+        self.rx_timed_out.replace(false)
+    }
+
+    /// Internal helper function for programming the alarm for the earliest
+    /// pending console timer, or disarming it if there is none.
+    fn arm_alarm(&self) {
+        let now = self.alarm.now();
+        let next = [
+            self.rx_idle_timer.extract(),
+            self.rx_timeout_timer.extract(),
+            self.loopback_timer.extract(),
+            self.tx_throttle_timer.extract(),
+            self.tx_gap_timer.extract(),
+        ]
+        .iter()
+        .flatten()
+        .map(|&(reference, dt)| {
+            let elapsed = now.wrapping_sub(reference);
+            if elapsed >= dt {
+                A::Ticks::from(0)
+            } else {
+                dt.wrapping_sub(elapsed)
+            }
+        })
+        .min();
+        match next {
+            Some(remaining) => self.alarm.set_alarm(now, remaining),
+            None => {
+                let _ = self.alarm.disarm();
+            }
         }
     }
 }
 
-impl SyscallDriver for Console<'_> {
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> SyscallDriver for Console<'a, U, A> {
     /// Setup shared buffers.
     ///
     /// ### `allow_num`
     ///
     /// - `1`: Writeable buffer for read buffer
+    ///
+    /// The buffer stays shared across reads until the app replaces it. Allowing
+    /// a zero-length buffer is how an app revokes the one it shared before, so
+    /// it is accepted. Reads started with an empty buffer fail with `INVAL`.
     fn allow_readwrite(
         &self,
         appid: ProcessId,
@@ -205,6 +2262,17 @@ impl SyscallDriver for Console<'_> {
     /// ### `allow_num`
     ///
     /// - `1`: Readonly buffer for write buffer
+    /// - `5` to `GATHER_ALLOW_MAX`: Readonly buffers for gather writes
+    ///
+    /// Allowing a zero-length buffer is how an app revokes the one it shared
+    /// before, so it is accepted. Writes started with an empty buffer fail
+    /// with `INVAL` and issue no transaction.
+    ///
+    /// A buffer is lent to the console until the write that uses it
+    /// completes: replacing or revoking it before the write callback fails
+    /// with `BUSY`, and the write goes on with the buffer it started with.
+    /// An app that wants to send more while a write is under way has to
+    /// wait for the callback, or cancel the write with command `8`.
     fn allow_readonly(
         &self,
         appid: ProcessId,
@@ -215,9 +2283,23 @@ impl SyscallDriver for Console<'_> {
             1 => self
                 .apps
                 .enter(appid, |app, _| {
+                    if app.write_uses(0) {
+                        return Err(ErrorCode::BUSY);
+                    }
                     mem::swap(&mut app.write_buffer, &mut slice);
+                    Ok(())
                 })
-                .map_err(ErrorCode::from),
+                .unwrap_or_else(|err| Err(err.into())),
+            5..=GATHER_ALLOW_MAX => self
+                .apps
+                .enter(appid, |app, _| {
+                    if app.write_uses(allow_num - 4) {
+                        return Err(ErrorCode::BUSY);
+                    }
+                    mem::swap(&mut app.gather_buffers[allow_num - 5], &mut slice);
+                    Ok(())
+                })
+                .unwrap_or_else(|err| Err(err.into())),
             _ => Err(ErrorCode::NOSUPPORT),
         };
 
@@ -234,6 +2316,18 @@ impl SyscallDriver for Console<'_> {
     //
     // - `1`: Write buffer completed callback
     // - `2`: Read buffer completed callback
+    // - `3`: Line event callback, see `LineEvent`
+    // - `4`: Write progress callback, issued after each chunk of a write
+    //        that needs more than one transaction, with the number of bytes
+    //        written so far in `r0` and the number still to go in `r1`
+    // - `5`: Break callback, issued when the UART detects a break condition
+    //        on the line, with the number of bytes of the current read
+    //        received before the break in `r0`. The read itself also ends
+    //        with a break error.
+    // - `6`: Loopback self-test callback, with success or the error in `r0`
+    //        and the number of bytes that came back correctly in `r1`
+    // - `7`: Flush callback, issued when a flush requested with command `26`
+    //        completes
 
     /// Initiate serial transfers
     ///
@@ -241,41 +2335,547 @@ impl SyscallDriver for Console<'_> {
     ///
     /// - `0`: Driver check.
     /// - `1`: Transmits a buffer passed via `allow`, up to the length
-    ///        passed in `arg1`
+    ///        passed in `arg1`. Returns `INVAL` if the shared buffer is
+    ///        empty, and `BUSY` if a previous write is still in progress.
+    ///        If `arg2` has `ATOMIC_WRITE` set, no other app's write starts
+    ///        once this one has, until it is done, so that its output is not
+    ///        interleaved with theirs. If `arg2` has `APPEND_NEWLINE` set, a
+    ///        `\n` is sent after the buffer's bytes. With `HEX_DUMP`, each
+    ///        byte is sent as two hex digits, and with `HEX_DUMP_SPACED`
+    ///        pairs are also separated by spaces. Lengths reported to the
+    ///        write callback count the bytes sent, including these.
     /// - `2`: Receives into a buffer passed via `allow`, up to the length
-    ///        passed in `arg1`
+    ///        passed in `arg1`. The read is shortened to the length of the
+    ///        buffer, but has no other limit: reads longer than the kernel
+    ///        receive buffer (see `6`) are received in chunks. Returns
+    ///        `INVAL` if the shared buffer is empty, and `BUSY` if a read
+    ///        is already in progress on the console. If `arg2` has
+    ///        `READ_DELIMITER` set, its low byte is a delimiter: the read
+    ///        also completes as soon as that byte arrives, and its length
+    ///        includes the delimiter. If the
+    ///        app shrinks or revokes the buffer before the read completes,
+    ///        the callback reports `SIZE` (or `NOMEM` if the buffer is gone)
+    ///        and the number of received bytes that did not fit in `r2`. If
+    ///        the UART reports a receive error, the callback gets no data, an
+    ///        error code in `r0` and the kind of error in `r2`. Each kind has
+    ///        its own error code: parity errors give `INVAL` and 1, framing
+    ///        errors `FAIL` and 2, overruns `SIZE` and 3, repeated calls
+    ///        `BUSY` and 4, a UART reset `OFF` and 5, and a break condition
+    ///        `NOACK` and 7. Kind 0 means no error.
     /// - `3`: Cancel any in progress receives and return (via callback)
-    ///        what has been received so far.
-    fn command(&self, cmd_num: usize, arg1: usize, _: usize, appid: ProcessId) -> CommandReturn {
-        let res = self
-            .apps
-            .enter(appid, |app, _| {
+    ///        what has been received so far. The read callback reports
+    ///        `CANCEL` and the number of bytes received. Returns `ALREADY` if
+    ///        no read was in progress, or `FAIL` if the UART could not cut
+    ///        the read short, in which case it completes normally.
+    /// - `4`: Check whether the UART can generate the baud rate passed in
+    ///        `arg1` to within `BAUD_RATE_TOLERANCE`. Returns success if it
+    ///        can, `INVAL` if it cannot, and the nearest achievable rate in
+    ///        both cases. Returns `NOSUPPORT` if the UART cannot tell.
+    /// - `5`: Set the RX idle period in milliseconds, passed in `arg1`. Once
+    ///        a read has received at least one byte, a line event is
+    ///        delivered whenever the line stays idle for this long; the read
+    ///        itself carries on. `0` (the default) disables idle detection.
+    /// - `6`: Report the capacity of the kernel receive buffer, which is the
+    ///        largest chunk a read is received in, and the number of bytes of
+    ///        the current read received so far. With continuous receive on
+    ///        (`23`), report `RX_RING_LEN` and the number of bytes waiting
+    ///        in the ring instead.
+    /// - `7`: Only receive frames sent to the 9-bit multidrop node address
+    ///        in `arg1` if `arg2` is non-zero, or turn address filtering off
+    ///        if `arg2` is zero. Returns `NOSUPPORT` if the UART has no
+    ///        address-match hardware.
+    /// - `8`: Cancel the app's write and report (via callback) how many
+    ///        bytes were sent. Returns `1` if the UART cannot abort the
+    ///        transmission in progress, so the cancellation only takes effect
+    ///        once it drains, and `0` otherwise.
+    /// - `9`: Limit the app's output to `arg1` bytes per second, with bursts of
+    ///        up to one second's worth. Writes over the limit are held back
+    ///        until the app has earned more quota. `0` (the default) removes
+    ///        the limit.
+    /// - `10`: Turn CRC-16 framing on if `arg1` is non-zero, or off if it is
+    ///        zero. `arg2` selects the polynomial, or `DEFAULT_CRC_POLY` if it
+    ///        is zero. A framed write is followed by the big-endian CRC of its
+    ///        bytes. A framed read of `len` bytes receives `len` bytes plus a
+    ///        CRC trailer. The callback strips the trailer from the reported
+    ///        length and sends a `LineEvent::CrcMismatch` if it does not
+    ///        match. Lengths reported to the write and read callbacks never
+    ///        include the trailer.
+    /// - `11`: Set the baud rate of the UART to `arg1`. Returns `INVAL` for
+    ///        `0` or a rate command `4` rejects, and `BUSY` if any
+    ///        transmission or reception is in progress, as the line is
+    ///        shared by all apps.
+    /// - `12`: Return the baud rate the UART is currently configured with.
+    /// - `13`: Set the parity of the UART to `arg1` (`0` none, `1` odd, `2`
+    ///        even) and its stop bits to `arg2` (`1` or `2`). Returns `INVAL`
+    ///        for any other value, and `BUSY` as for `11`.
+    /// - `14`: Turn RTS/CTS hardware flow control on if `arg1` is non-zero,
+    ///        or off if it is zero. Returns `BUSY` as for `11`, and
+    ///        `NOSUPPORT` if the UART has no flow control.
+    /// - `15`: Echo bytes received for this app back out of the UART if
+    ///        `arg1` is non-zero, or stop if it is zero. Echoed bytes go out
+    ///        as kernel output, between the transactions of app writes
+    ///        (after the whole write, if it is atomic). Echo is best effort:
+    ///        of the bytes received during one transaction, only the first
+    ///        `ECHO_BUF_LEN` are echoed.
+    /// - `16`: Like `2`, but gives up after `arg2` milliseconds (`0` waits
+    ///        forever). On timeout the receive is aborted and the callback
+    ///        reports success with whatever was received so far. Aborting
+    ///        with `3` also cancels the timeout.
+    /// - `17`: Return the number of bytes the console has transmitted
+    ///        (`arg1` = 0) or received (`arg1` = 1) since boot, for all apps
+    ///        together. The counters wrap around.
+    /// - `18`: Receive a single byte without an allowed buffer. The read
+    ///        callback carries the byte in `r1`. Returns `BUSY` while any
+    ///        other read is in progress.
+    /// - `19`: Return whether the UART is currently transmitting (`1` or
+    ///        `0`), and whether the calling app has a write queued behind it.
+    ///        Changes no state.
+    /// - `20`: Drop the app's write if it is queued and has not started yet,
+    ///        without a write callback. Returns success if nothing is
+    ///        queued, and `BUSY` if part of the write has already been sent
+    ///        (use `8` to cancel it instead).
+    /// - `21`: Transmit the first `arg1` write buffers (`allow_num` 1, then 5,
+    ///        6, ...) back to back, in full, as a single write with a single
+    ///        callback. Returns `INVAL` unless `arg1` is between 1 and
+    ///        `GATHER_BUFFERS + 1`, and otherwise behaves like `1`, including
+    ///        the flags in `arg2`.
+    /// - `22`: Set the number of data bits per character to `arg1`, from 6
+    ///        to 9. Data is still transferred a byte at a time: in 9-bit mode
+    ///        the UART handles the ninth bit itself, e.g. for address
+    ///        matching with `7`. Returns `INVAL` for other widths,
+    ///        `NOSUPPORT` for widths the UART cannot do and `BUSY` as for
+    ///        `11`.
+    /// - `23`: Turn continuous receive on (`arg1` non-zero) or off. While it
+    ///        is on, the console keeps receiving into a ring of `RX_RING_LEN`
+    ///        bytes between reads, so nothing is lost while no read is armed,
+    ///        and reads are served from the ring. If the ring fills up, the
+    ///        next read callback reports how many bytes were dropped in `r2`.
+    ///        A UART error, such as a break, while no read is in progress
+    ///        ends the next read right away with that error; the bytes
+    ///        received before it stay in the ring. Returns `BUSY` while a
+    ///        read is in progress.
+    /// - `24`: Return the number of received bytes the console has lost since
+    ///        the calling app last asked (or since boot), because the ring was
+    ///        full or a read buffer was shrunk under a read. All apps see the
+    ///        same losses; each keeps its own count of what it has seen.
+    /// - `25`: Run a loopback self-test for board bring-up: loop the UART
+    ///        back internally, send a known pattern and check that it comes
+    ///        back. The result is delivered through the self-test callback,
+    ///        and the loopback setting is restored afterwards. Returns
+    ///        `NOSUPPORT` if the UART has no loopback mode and `BUSY` if any
+    ///        other transmission or reception is in progress. Output from
+    ///        other users of a shared UART is looped back too while the test
+    ///        runs.
+    /// - `26`: Flush. Issues the flush callback once the app's write in
+    ///        progress, if any, has completed and the console has nothing
+    ///        left to send. Unlike the write callback, this also waits for
+    ///        output the console sends on behalf of others, such as echo and
+    ///        other apps' writes. How soon after the last transmission the
+    ///        line really goes quiet depends on the UART, as some report a
+    ///        transmission done once its last byte is in the FIFO.
+    /// - `27`: Set the app's transmit priority to `arg1`. Queued writes from
+    ///        apps with a higher priority start first, and a write in
+    ///        progress yields to them between transactions unless it is
+    ///        atomic. Apps of equal priority take turns. The default is `0`.
+    /// - `28`: Return the number of apps, the caller included, that have
+    ///        shared a write or read buffer with the console.
+    /// - `29`: Turn read timestamps on (`arg1` non-zero) or off. While they
+    ///        are on, reads that complete as data arrives report in `r2`,
+    ///        instead of the dropped byte count, the low 32 bits of the
+    ///        console alarm's time when the UART handed the data over.
+    ///        Returns the alarm's frequency in Hz. Reads served from the ring
+    ///        with continuous receive on and reads ended by a receive error
+    ///        are not timestamped.
+    /// - `30`: Claim exclusive access to the console if `arg1` is non-zero,
+    ///        or release it if it is zero. While an app holds the claim,
+    ///        reads and writes from other apps fail with `BUSY`; those
+    ///        already under way finish normally, and kernel output is not
+    ///        affected. The claim is dropped if the app exits. Claiming
+    ///        returns `BUSY` if another app holds the claim, and releasing
+    ///        returns `RESERVE`.
+    /// - `31`: Transmit the byte in the low 8 bits of `arg1`, repeated `arg2`
+    ///        times, without an allowed buffer, e.g. to put a test pattern on
+    ///        the line. It completes like a write from `1`, with the write
+    ///        callback. Returns `INVAL` if `arg2` is zero and `BUSY` if a
+    ///        previous write is still in progress.
+    /// - `32`: Return how many bytes of the app's write the UART has not yet
+    ///        reported transmitted: those still waiting in the app's buffer,
+    ///        whether the write is queued or under way, plus those in the
+    ///        transaction in flight.
+    /// - `33`: Start a streaming read into the buffer passed via `allow`.
+    ///        Instead of completing once, the read issues a read callback
+    ///        for every run of bytes as soon as it has arrived, with the
+    ///        number of bytes in `r1` and their offset in the buffer in `r2`,
+    ///        and the UART is re-armed right away. The bytes fill the buffer
+    ///        from the start and wrap around at its end, so the buffer stays
+    ///        shared with the console for the whole stream: an app that
+    ///        falls more than the buffer's length behind loses the oldest
+    ///        data. The stream ends only when it is aborted with `3`, or on
+    ///        a receive error, with a final read callback that carries
+    ///        `CANCEL` (or the error) and no bytes. Delimiters, idle
+    ///        detection, timeouts, timestamps and CRC framing do not apply.
+    ///        Returns `INVAL` if the shared buffer is empty and `BUSY` if a
+    ///        read is already in progress.
+    /// - `34`: Return the capacity of the kernel transmit buffer, which is
+    ///        the largest chunk a write is transmitted in. A write of `n`
+    ///        bytes takes at least `n` divided by this, rounded up,
+    ///        transactions (more if it is throttled or preempted), plus one
+    ///        for the CRC trailer if framing is on.
+    /// - `35`: Return whether the app's last write has completed (`1`) or
+    ///        is still queued or under way (`0`), for apps that poll instead
+    ///        of subscribing to the write callback.
+    /// - `36`: Turn RS-485 half-duplex mode on (`arg1` non-zero) or off.
+    ///        While it is on, the console enables the transceiver's driver
+    ///        (and disables its receiver) before each transmission, and
+    ///        hands the line back once it has nothing left to send. This
+    ///        relies on the UART reporting a transmission done only once its
+    ///        last byte has left the line. Returns `NOSUPPORT` if the board
+    ///        has no transceiver pins and `BUSY` while transmitting.
+    /// - `37`: Set the RX idle period in character-times, passed in `arg1`,
+    ///        as an alternative to `5` for protocols that frame on idle
+    ///        time. A character-time is how long one character takes at the
+    ///        current baud rate, parity and stop bits. Setting either period
+    ///        replaces the other, and `0` disables idle detection.
+    /// - `38`: Return the kind of the UART error that last ended one of the
+    ///        app's reads, numbered as for the read callback (see `2`), or
+    ///        `0` if the UART has received data for the app since.
+    /// - `39`: Pause the app's read in progress without ending it. What it
+    ///        has received so far stays in its buffer, and no other app can
+    ///        start a read until it is resumed or aborted (`3`). Without
+    ///        continuous receive, a chunk already handed to the UART is still
+    ///        received in full, so the pause takes effect at the next chunk
+    ///        boundary: a read that fits in one chunk (see `6`) is not
+    ///        paused at all unless it is received a byte at a time for idle
+    ///        detection or a delimiter. Bytes arriving while paused are lost,
+    ///        except with continuous receive, where they wait in the ring
+    ///        until it overflows. A read timeout keeps running. Returns
+    ///        `ALREADY` if the app has no read in progress or it is already
+    ///        paused.
+    /// - `40`: Resume the app's paused read. Returns `ALREADY` if the app
+    ///        has no paused read.
+    /// - `41`: Return the console's capabilities as a bitmask of the `CAP_*`
+    ///        constants, described in the module documentation.
+    /// - `42`: Leave a gap of `arg1` microseconds after every transmission,
+    ///        for peers that drop bytes sent back to back. While a gap is
+    ///        set, writes go out one byte per transmission, each app's
+    ///        write as a whole before the next app's, and the write progress
+    ///        callback still comes once per chunk (see `34`); kernel output
+    ///        keeps its chunks but also waits out the gap. This costs
+    ///        throughput: each byte takes its character-time plus the gap
+    ///        and an alarm interrupt. The gap is shared by the whole line,
+    ///        so it slows every app's output. `0` (the default) sends back
+    ///        to back. Returns `INVAL` for gaps above `MAX_TX_GAP_US`.
+    /// - `43`: Discard received bytes that no read has taken yet, e.g. stale
+    ///        input at the start of an interactive session, so the next read
+    ///        only gets what arrives after the call. With continuous receive
+    ///        this empties the ring; otherwise the UART drops what it holds
+    ///        in its FIFO. Returns `BUSY` if a read is in progress, as its
+    ///        bytes would be lost, and `NOSUPPORT` if the UART cannot
+    ///        discard its data.
+    /// - `44`: Copy up to `arg1` received bytes waiting in the ring into the
+    ///        buffer passed via `allow`, and return how many were copied,
+    ///        without starting a read or issuing a callback. Returns `0` if
+    ///        the ring is empty, which it always is unless continuous
+    ///        receive (`23`) is on. This lets event-loop apps poll for input
+    ///        between other work. A UART error the ring kept for the next
+    ///        read is reported by `38` instead. Returns `INVAL` if the shared
+    ///        buffer is empty and `BUSY` if a read is in progress, as the
+    ///        ring's bytes go to that read.
+    fn command(&self, cmd_num: usize, arg1: usize, arg2: usize, appid: ProcessId) -> CommandReturn {
+        self.apps
+            .enter(appid, |app, upcalls| {
                 match cmd_num {
-                    0 => Ok(()),
+                    0 => CommandReturn::success(),
                     1 => {
                         // putstr
                         let len = arg1;
-                        self.send_new(appid, app, len)
+                        self.send_new(appid, app, len, 1, arg2).into()
                     }
                     2 => {
                         // getnstr
                         let len = arg1;
-                        self.receive_new(appid, app, len)
+                        let delimiter = if arg2 & READ_DELIMITER != 0 {
+                            Some(arg2 as u8)
+                        } else {
+                            None
+                        };
+                        self.receive_new(appid, app, upcalls, len, delimiter).into()
                     }
                     3 => {
                         // Abort RX
-                        let _ = self.uart.receive_abort();
-                        Ok(())
+                        self.rx_timeout_timer.clear();
+                        if self.rx_continuous.get() {
+                            // Reads are served from the ring, so there is no
+                            // UART receive to abort.
+                            let cancel = Err(ErrorCode::CANCEL);
+                            match self.rx_in_progress.extract() {
+                                Some(reader) if reader == appid => {
+                                    self.finish_ring_read(app, upcalls, cancel, None);
+                                    CommandReturn::success()
+                                }
+                                Some(reader) => {
+                                    let _ = self.apps.enter(reader, |app, upcalls| {
+                                        self.finish_ring_read(app, upcalls, cancel, None)
+                                    });
+                                    CommandReturn::success()
+                                }
+                                None => CommandReturn::failure(ErrorCode::ALREADY),
+                            }
+                        } else if self.rx_in_progress.is_some() && self.rx_buffer.is_some() {
+                            // A paused read has nothing at the UART to abort.
+                            self.rx_in_progress.extract().map(|reader| {
+                                let cancel = Err(ErrorCode::CANCEL);
+                                if reader == appid {
+                                    self.finish_ring_read(app, upcalls, cancel, None);
+                                } else {
+                                    let _ = self.apps.enter(reader, |app, upcalls| {
+                                        self.finish_ring_read(app, upcalls, cancel, None)
+                                    });
+                                }
+                            });
+                            CommandReturn::success()
+                        } else {
+                            let abort = self
+                                .rx_in_progress
+                                .is_some()
+                                .then(|| self.uart.receive_abort());
+                            rx_abort_result(abort).into()
+                        }
+                    }
+                    4 => {
+                        // Check baud rate
+                        self.check_baud_rate(arg1 as u32)
+                    }
+                    5 => {
+                        // Set RX idle period
+                        app.rx_idle_ms = arg1;
+                        app.rx_idle_chars = 0;
+                        CommandReturn::success()
+                    }
+                    6 => {
+                        // RX buffer status
+                        self.rx_buffer_status(appid, app)
+                    }
+                    7 => {
+                        // Multidrop address filter
+                        let address = if arg2 != 0 { Some(arg1 as u8) } else { None };
+                        self.uart.set_address_match(address).into()
+                    }
+                    8 => {
+                        // Abort TX
+                        self.abort_write(appid, app, upcalls)
+                    }
+                    9 => {
+                        // Set TX rate limit
+                        app.tx_rate_limit = arg1;
+                        app.tx_tokens = arg1;
+                        app.tx_refilled_at = self.alarm.now().into_u32();
+                        CommandReturn::success()
+                    }
+                    10 => {
+                        // CRC framing
+                        app.crc_poly = if arg1 != 0 {
+                            Some(if arg2 != 0 {
+                                arg2 as u16
+                            } else {
+                                DEFAULT_CRC_POLY
+                            })
+                        } else {
+                            None
+                        };
+                        CommandReturn::success()
+                    }
+                    11 => {
+                        // Set baud rate
+                        self.set_baud_rate(arg1 as u32)
+                    }
+                    12 => {
+                        // Get baud rate
+                        CommandReturn::success_u32(self.parameters.get().baud_rate)
+                    }
+                    13 => {
+                        // Set parity and stop bits
+                        self.set_framing(arg1, arg2)
+                    }
+                    14 => {
+                        // Set hardware flow control
+                        self.reconfigure(uart::Parameters {
+                            hw_flow_control: arg1 != 0,
+                            ..self.parameters.get()
+                        })
+                    }
+                    15 => {
+                        // Set echo
+                        app.echo = arg1 != 0;
+                        CommandReturn::success()
+                    }
+                    16 => {
+                        // getnstr with timeout
+                        let (len, timeout_ms) = (arg1, arg2);
+                        let result = self.receive_new(appid, app, upcalls, len, None);
+                        // A read served from the ring can complete at once.
+                        if result.is_ok() && timeout_ms > 0 && self.rx_in_progress.contains(&appid)
+                        {
+                            self.start_rx_timeout_timer(timeout_ms);
+                        }
+                        result.into()
+                    }
+                    17 => {
+                        // Byte counters
+                        match arg1 {
+                            0 => CommandReturn::success_u32(self.tx_bytes.get() as u32),
+                            1 => CommandReturn::success_u32(self.rx_bytes.get() as u32),
+                            _ => CommandReturn::failure(ErrorCode::INVAL),
+                        }
+                    }
+                    18 => {
+                        // getchar
+                        self.receive_byte(appid, app, upcalls).into()
+                    }
+                    19 => {
+                        // Transmitter status
+                        let busy =
+                            self.tx_in_progress.is_some() || self.kernel_tx_in_progress.get();
+                        CommandReturn::success_u32_u32(busy as u32, app.pending_write as u32)
+                    }
+                    20 => {
+                        // Drop queued write
+                        self.drop_pending_write(app).into()
+                    }
+                    21 => {
+                        // Gather write
+                        if arg1 == 0 || arg1 > 1 + GATHER_BUFFERS {
+                            CommandReturn::failure(ErrorCode::INVAL)
+                        } else {
+                            self.send_new(appid, app, usize::MAX, arg1, arg2).into()
+                        }
+                    }
+                    22 => {
+                        // Set data width
+                        let width = match arg1 {
+                            6 => uart::Width::Six,
+                            7 => uart::Width::Seven,
+                            8 => uart::Width::Eight,
+                            9 => uart::Width::Nine,
+                            _ => return CommandReturn::failure(ErrorCode::INVAL),
+                        };
+                        self.reconfigure(uart::Parameters {
+                            width: width,
+                            ..self.parameters.get()
+                        })
+                    }
+                    23 => {
+                        // Continuous receive
+                        self.set_continuous_rx(arg1 != 0)
+                    }
+                    24 => {
+                        // Dropped byte count
+                        let total = self.rx_dropped.get();
+                        let dropped = total.wrapping_sub(app.rx_dropped_seen);
+                        app.rx_dropped_seen = total;
+                        CommandReturn::success_u32(dropped as u32)
+                    }
+                    25 => {
+                        // Loopback self-test
+                        self.start_loopback_test(appid).into()
                     }
-                    _ => Err(ErrorCode::NOSUPPORT),
+                    26 => {
+                        // Flush
+                        self.flush(app, upcalls);
+                        CommandReturn::success()
+                    }
+                    27 => {
+                        // Set TX priority
+                        app.tx_priority = arg1;
+                        CommandReturn::success()
+                    }
+                    28 => {
+                        // Number of apps using the console
+                        CommandReturn::success_u32(self.active_apps(appid, app) as u32)
+                    }
+                    29 => {
+                        // Read timestamps
+                        app.rx_timestamps = arg1 != 0;
+                        CommandReturn::success_u32(A::Frequency::frequency())
+                    }
+                    30 => {
+                        // Exclusive access
+                        self.set_exclusive(appid, arg1 != 0).into()
+                    }
+                    31 => {
+                        // Fill write
+                        self.send_fill(appid, app, arg1 as u8, arg2).into()
+                    }
+                    32 => {
+                        // TX backlog
+                        let in_flight = if self.tx_in_progress.contains(&appid) {
+                            app.tx_chunk_len
+                        } else {
+                            0
+                        };
+                        CommandReturn::success_u32((app.write_remaining + in_flight) as u32)
+                    }
+                    33 => {
+                        // Streaming read
+                        self.receive_stream(appid, app, upcalls).into()
+                    }
+                    34 => {
+                        // TX chunk size
+                        CommandReturn::success_u32(self.tx_capacity as u32)
+                    }
+                    35 => {
+                        // Write completed
+                        let done = app.write_len == 0 && !app.pending_write;
+                        CommandReturn::success_u32(done as u32)
+                    }
+                    36 => {
+                        // RS-485 half-duplex
+                        self.set_rs485(arg1 != 0).into()
+                    }
+                    37 => {
+                        // Set RX idle period in character-times
+                        app.rx_idle_chars = arg1;
+                        app.rx_idle_ms = 0;
+                        CommandReturn::success()
+                    }
+                    38 => {
+                        // Last receive error
+                        let kind = app.rx_last_error.map_or(0, |e| rx_error_report(e).1);
+                        CommandReturn::success_u32(kind as u32)
+                    }
+                    39 => {
+                        // Pause RX
+                        self.pause_read(appid, app, upcalls, true).into()
+                    }
+                    40 => {
+                        // Resume RX
+                        self.pause_read(appid, app, upcalls, false).into()
+                    }
+                    41 => {
+                        // Capabilities
+                        CommandReturn::success_u32(self.capabilities())
+                    }
+                    42 => {
+                        // Set inter-byte gap
+                        if arg1 > MAX_TX_GAP_US as usize {
+                            CommandReturn::failure(ErrorCode::INVAL)
+                        } else {
+                            self.tx_gap_us.set(arg1 as u32);
+                            CommandReturn::success()
+                        }
+                    }
+                    43 => {
+                        // Flush RX
+                        self.flush_rx().into()
+                    }
+                    44 => {
+                        // Non-blocking read
+                        self.read_available(appid, app, arg1)
+                    }
+                    _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
                 }
             })
-            .map_err(ErrorCode::from);
-        match res {
-            Ok(Ok(())) => CommandReturn::success(),
-            Ok(Err(e)) => CommandReturn::failure(e),
-            Err(e) => CommandReturn::failure(e),
-        }
+            .unwrap_or_else(|err| err.into())
     }
 
     fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
@@ -283,54 +2883,128 @@ impl SyscallDriver for Console<'_> {
     }
 }
 
-impl uart::TransmitClient for Console<'_> {
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> uart::TransmitClient for Console<'a, U, A> {
     fn transmitted_buffer(
         &self,
         buffer: &'static mut [u8],
-        _tx_len: usize,
-        _rcode: Result<(), ErrorCode>,
+        tx_len: usize,
+        rcode: Result<(), ErrorCode>,
     ) {
         // Either print more from the AppSlice or send a callback to the
         // application.
+        self.tx_bytes.set(self.tx_bytes.get().wrapping_add(tx_len));
         self.tx_buffer.replace(buffer);
+        self.kernel_tx_in_progress.set(false);
+        self.start_tx_gap_timer();
+        let waiting_priority = self.top_pending_priority(None);
         self.tx_in_progress.take().map(|appid| {
-            self.apps.enter(appid, |app, upcalls| {
-                match self.send_continue(appid, app) {
-                    true => {
-                        // Still more to send. Wait to notify the process.
+            let entered = self.apps.enter(appid, |app, upcalls| {
+                let unsent = app.unsent(tx_len);
+                let resumable = matches!(rcode, Ok(()) | Err(ErrorCode::CANCEL));
+                if self.tx_preempted.contains(&appid) && resumable && !app.cancel_write {
+                    // An urgent message went ahead of this transaction: put
+                    // back what the UART did not get out and resume the write
+                    // after the message.
+                    self.tx_buffer
+                        .map(|buffer| app.requeue_unsent(unsent, buffer));
+                    if app.write_remaining > 0 || app.tx_crc_pending {
+                        app.pending_write = true;
+                        let written = app.write_len - app.write_remaining;
+                        upcalls
+                            .schedule_upcall(4, (written, app.write_remaining, 0))
+                            .ok();
+                    } else {
+                        self.tx_preempted.clear();
+                        self.finish_write(appid, app, upcalls, Ok(()));
+                    }
+                    return;
+                }
+                self.tx_preempted.clear();
+                if app.cancel_write {
+                    // The app cancelled this write: drop whatever has not yet
+                    // been handed to the UART, and whatever of this
+                    // transaction the UART did not get out before the abort.
+                    app.cancel_write = false;
+                    app.truncate_write(unsent);
+                } else if let Err(ecode) = rcode {
+                    // The UART failed this transaction: end the write with
+                    // what made it out and tell the app why.
+                    app.truncate_write(unsent);
+                    self.finish_write(appid, app, upcalls, Err(ecode));
+                    return;
+                } else if unsent > 0 {
+                    // The UART only sent part of the transaction: the rest
+                    // goes out next.
+                    self.tx_buffer
+                        .map(|buffer| app.requeue_unsent(unsent, buffer));
+                }
+                let written = app.write_len - app.write_remaining;
+                let remaining = app.write_remaining;
+                let next = next_transaction(
+                    remaining > 0 || app.tx_crc_pending,
+                    app.atomic_write,
+                    waiting_priority.map_or(false, |p| p > app.tx_priority),
+                    self.echo_len.get() > 0,
+                );
+                match next {
+                    TxNext::Continue => {}
+                    TxNext::YieldToPriority => {
+                        // A higher-priority app is waiting: queue the rest of
+                        // this write behind it.
+                        app.pending_write = true;
+                        self.report_progress(upcalls, written, remaining);
+                        return;
                     }
-                    false => {
-                        // Go ahead and signal the application
-                        let written = app.write_len;
-                        app.write_len = 0;
-                        upcalls.schedule_upcall(1, (written, 0, 0)).ok();
+                    TxNext::YieldToEcho => {
+                        // Echoed bytes go out between the transactions of the
+                        // write, which then resumes ahead of other apps.
+                        app.pending_write = true;
+                        self.tx_preempted.set(appid);
+                        self.report_progress(upcalls, written, remaining);
+                        return;
                     }
                 }
-            })
-        });
-
-        // If we are not printing more from the current AppSlice,
-        // see if any other applications have pending messages.
-        if self.tx_in_progress.is_none() {
-            for cntr in self.apps.iter() {
-                let appid = cntr.processid();
-                let started_tx = cntr.enter(|app, _upcalls| {
-                    if app.pending_write {
-                        app.pending_write = false;
-                        self.send_continue(appid, app)
-                    } else {
-                        false
+                match self.send_continue(appid, app) {
+                    Ok(true) => {
+                        // Still more to send. Wait to notify the process
+                        // of completion, but report progress to it.
+                        self.report_progress(upcalls, written, remaining);
+                        if self.tx_gap_timer.is_some() {
+                            // The rest waits out the gap.
+                            self.tx_gap_next.set(appid);
+                        }
                     }
-                });
-                if started_tx {
-                    break;
+                    // Go ahead and signal the application
+                    Ok(false) => self.finish_write(appid, app, upcalls, Ok(())),
+                    Err(ecode) => self.finish_write(appid, app, upcalls, Err(ecode)),
+                }
+            });
+            if entered.is_err() {
+                // The app went away while its write was draining. There is
+                // nobody left to notify and its grant is no longer scanned,
+                // so drop any hold it had on the transmitter and move on to
+                // the other apps below; `tx_buffer` is already back.
+                if self.tx_held.contains(&appid) {
+                    self.tx_held.clear();
+                }
+                if self.tx_preempted.contains(&appid) {
+                    self.tx_preempted.clear();
                 }
             }
+        });
+
+        // If we are not printing more from the current AppSlice, kernel
+        // output (echo included) goes next, then any other applications'
+        // pending messages.
+        if self.tx_in_progress.is_none() && !self.send_kernel() {
+            self.send_pending();
         }
+
+        self.finish_line();
     }
 }
 
-impl uart::ReceiveClient for Console<'_> {
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> uart::ReceiveClient for Console<'a, U, A> {
     fn received_buffer(
         &self,
         buffer: &'static mut [u8],
@@ -338,28 +3012,168 @@ impl uart::ReceiveClient for Console<'_> {
         rcode: Result<(), ErrorCode>,
         error: uart::Error,
     ) {
+        self.rx_bytes.set(self.rx_bytes.get().wrapping_add(rx_len));
+
+        if let Some(appid) = self.loopback_test.take() {
+            self.finish_loopback_test(appid, &buffer[..cmp::min(rx_len, buffer.len())], error);
+            self.rx_buffer.replace(buffer);
+            return;
+        }
+
+        if self.rx_to_ring.replace(false) {
+            // Continuous receive: queue the bytes and keep receiving, then
+            // hand what is queued to the read in progress.
+            for &byte in buffer.iter().take(rx_len) {
+                if !self.rx_ring.push(byte) {
+                    self.count_rx_dropped(1);
+                }
+            }
+            self.rx_buffer.replace(buffer);
+            if self.rx_continuous.get() {
+                self.receive_to_ring();
+            }
+            match self.rx_in_progress.extract() {
+                Some(appid) => {
+                    let _ = self.apps.enter(appid, |app, upcalls| match error {
+                        uart::Error::None | uart::Error::Aborted => {
+                            if error == uart::Error::None && rx_len > 0 {
+                                app.rx_last_error = None;
+                            }
+                            self.drain_ring(app, upcalls)
+                        }
+                        _ => {
+                            self.rx_in_progress.clear();
+                            self.receive_error(app, upcalls, error);
+                        }
+                    });
+                }
+                None if error != uart::Error::None && error != uart::Error::Aborted => {
+                    // No read to end: the next one reports the error.
+                    self.rx_ring.set_error(error);
+                }
+                None => {}
+            }
+            return;
+        }
+
+        // Set if the read is not finished yet and the UART needs to be armed
+        // for the rest of it once `buffer` is back in place.
+        let mut continue_read = None;
+
         self.rx_in_progress
             .take()
             .map(|appid| {
                 self.apps
                     .enter(appid, |app, upcalls| {
+                        // Only read the clock for apps that asked for it.
+                        let timestamp = app
+                            .rx_timestamps
+                            .then(|| self.alarm.now().into_u32() as usize);
+                        if error == uart::Error::None && rx_len > 0 {
+                            app.rx_last_error = None;
+                        }
                         // An iterator over the returned buffer yielding only the first `rx_len`
                         // bytes
                         let rx_buffer = buffer.iter().take(rx_len);
                         match error {
+                            uart::Error::None | uart::Error::Aborted if app.read_byte => {
+                                // Single byte read: hand the byte over in
+                                // `r1` rather than through a buffer.
+                                app.read_byte = false;
+                                self.stop_rx_timers();
+                                let byte = buffer.iter().take(rx_len).next().copied();
+                                let ret = match byte {
+                                    Some(b) => {
+                                        if app.echo {
+                                            self.echo(core::iter::once(b));
+                                        }
+                                        Ok(())
+                                    }
+                                    None if rcode.is_err() => rcode,
+                                    None => Err(ErrorCode::FAIL),
+                                };
+                                upcalls
+                                    .schedule_upcall(
+                                        2,
+                                        (
+                                            kernel::errorcode::into_statuscode(ret),
+                                            byte.unwrap_or(0) as usize,
+                                            timestamp.unwrap_or(0),
+                                        ),
+                                    )
+                                    .ok();
+                            }
+                            uart::Error::None | uart::Error::Aborted if app.read_stream => {
+                                self.stream_chunk(app, upcalls, rx_buffer.copied());
+                                if error == uart::Error::None && rcode == Ok(()) {
+                                    // Keep the stream going.
+                                    continue_read = Some(appid);
+                                } else {
+                                    // The stream was aborted.
+                                    app.read_stream = false;
+                                    self.stop_rx_timers();
+                                    let ret = rcode.and(Err(ErrorCode::CANCEL));
+                                    upcalls
+                                        .schedule_upcall(
+                                            2,
+                                            (kernel::errorcode::into_statuscode(ret), 0, 0),
+                                        )
+                                        .ok();
+                                }
+                            }
                             uart::Error::None | uart::Error::Aborted => {
                                 // Receive some bytes, signal error type and return bytes to process buffer
-                                let count = app
+                                let offset = app.read_offset;
+                                let delimiter = app.read_delimiter;
+                                let (count, delimited) = app
                                     .read_buffer
                                     .mut_enter(|data| {
                                         let mut c = 0;
-                                        for (a, b) in data.iter().zip(rx_buffer) {
+                                        for (a, b) in data.iter().skip(offset).zip(rx_buffer) {
                                             c = c + 1;
                                             a.set(*b);
+                                            if delimiter == Some(*b) {
+                                                return (c, true);
+                                            }
                                         }
-                                        c
+                                        (c, false)
                                     })
-                                    .unwrap_or(-1);
+                                    .unwrap_or((-1, false));
+                                // A delimiter ends the read right after it,
+                                // even if the UART returned more bytes.
+                                let rx_len = if delimited { count as usize } else { rx_len };
+                                if app.echo && count > 0 {
+                                    self.echo(buffer.iter().take(count as usize).copied());
+                                }
+
+                                // Bytes that did not fit in the app's buffer,
+                                // because it was shrunk or revoked while the
+                                // read was in progress.
+                                let dropped = if count < 0 {
+                                    rx_len
+                                } else {
+                                    rx_len - count as usize
+                                };
+                                self.count_rx_dropped(dropped);
+
+                                if count > 0
+                                    && dropped == 0
+                                    && !delimited
+                                    && error == uart::Error::None
+                                    && rcode == Ok(())
+                                    && offset + (count as usize) < app.read_len
+                                {
+                                    // Only part of the read has arrived:
+                                    // keep receiving without notifying the
+                                    // app.
+                                    app.read_offset += count as usize;
+                                    if app.detects_idle() {
+                                        self.start_rx_idle_timer(app);
+                                    }
+                                    continue_read = Some(appid);
+                                    return;
+                                }
+                                let timed_out = self.stop_rx_timers();
 
                                 // Make sure we report the same number
                                 // of bytes that we actually copied into
@@ -377,48 +3191,56 @@ impl uart::ReceiveClient for Console<'_> {
                                 //
                                 // If count < 0 this means the buffer
                                 // disappeared: return NOMEM.
-                                let (ret, received_length) = if count < 0 {
+                                let (ret, mut received_length) = if count < 0 {
                                     (Err(ErrorCode::NOMEM), 0)
-                                } else if rx_len > app.read_buffer.len() {
+                                } else if offset + rx_len > app.read_buffer.len() {
                                     // Return `SIZE` indicating that
-                                    // some received bytes were dropped.
+                                    // some received bytes were dropped,
+                                    // and how many in `r2`.
                                     // We report the length that we
                                     // actually copied into the buffer,
                                     // but also indicate that there was
                                     // an issue in the kernel with the
                                     // receive.
                                     (Err(ErrorCode::SIZE), app.read_buffer.len())
+                                } else if timed_out {
+                                    // The read timed out: the bytes that
+                                    // did arrive are a normal result.
+                                    (Ok(()), offset + rx_len)
                                 } else {
                                     // This is the normal and expected
                                     // case.
-                                    (rcode, rx_len)
+                                    (rcode, offset + rx_len)
                                 };
 
+                                if let (Some(poly), Ok(())) = (app.crc_poly, ret) {
+                                    let (payload_len, matched) =
+                                        self.check_rx_crc(app, poly, received_length);
+                                    received_length = payload_len;
+                                    if !matched {
+                                        upcalls
+                                            .schedule_upcall(
+                                                3,
+                                                (LineEvent::CrcMismatch as usize, payload_len, 0),
+                                            )
+                                            .ok();
+                                    }
+                                }
+
                                 upcalls
                                     .schedule_upcall(
                                         2,
                                         (
                                             kernel::errorcode::into_statuscode(ret),
                                             received_length,
-                                            0,
+                                            timestamp.unwrap_or(dropped),
                                         ),
                                     )
                                     .ok();
                             }
                             _ => {
                                 // Some UART error occurred
-                                upcalls
-                                    .schedule_upcall(
-                                        2,
-                                        (
-                                            kernel::errorcode::into_statuscode(Err(
-                                                ErrorCode::FAIL,
-                                            )),
-                                            0,
-                                            0,
-                                        ),
-                                    )
-                                    .ok();
+                                self.receive_error(app, upcalls, error);
                             }
                         }
                     })
@@ -428,5 +3250,504 @@ impl uart::ReceiveClient for Console<'_> {
 
         // Whatever happens, we want to make sure to replace the rx_buffer for future transactions
         self.rx_buffer.replace(buffer);
+
+        continue_read.map(|appid| {
+            if self.rx_paused.get() {
+                // Hold the line for the read until it is resumed.
+                self.rx_in_progress.set(appid);
+            } else {
+                let _ = self
+                    .apps
+                    .enter(appid, |app, upcalls| self.receive_next(appid, app, upcalls));
+            }
+        });
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: Alarm<'a>> AlarmClient for Console<'a, U, A> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        let expired = |timer: &OptionalCell<(A::Ticks, A::Ticks)>| {
+            timer
+                .extract()
+                .map_or(false, |(reference, dt)| now.wrapping_sub(reference) >= dt)
+        };
+
+        if expired(&self.rx_idle_timer) {
+            self.rx_idle_timer.clear();
+            self.rx_in_progress.extract().map(|appid| {
+                let _ = self.apps.enter(appid, |app, upcalls| {
+                    upcalls
+                        .schedule_upcall(3, (LineEvent::RxIdle as usize, app.read_offset, 0))
+                        .ok();
+                });
+            });
+        }
+
+        if expired(&self.loopback_timer) {
+            self.loopback_timer.clear();
+            if self.loopback_test.is_some() {
+                // The self-test's bytes did not all come back.
+                let _ = self.uart.receive_abort();
+            }
+        }
+
+        if expired(&self.rx_timeout_timer) {
+            self.rx_timeout_timer.clear();
+            if self.rx_in_progress.is_some() {
+                // Deliver what has arrived so far through the read callback.
+                self.rx_timed_out.set(true);
+                if self.rx_continuous.get() || self.rx_buffer.is_some() {
+                    // Served from the ring, or paused with nothing at the
+                    // UART to abort.
+                    self.rx_in_progress.extract().map(|appid| {
+                        let _ = self.apps.enter(appid, |app, upcalls| {
+                            self.finish_ring_read(app, upcalls, Ok(()), None)
+                        });
+                    });
+                } else {
+                    let _ = self.uart.receive_abort();
+                }
+            }
+        }
+
+        if expired(&self.tx_throttle_timer) {
+            self.tx_throttle_timer.clear();
+            if self.tx_in_progress.is_none() && !self.kernel_tx_in_progress.get() {
+                self.send_pending();
+            }
+        }
+
+        if expired(&self.tx_gap_timer) {
+            self.tx_gap_timer.clear();
+            // The write the gap interrupted carries on, as it would have
+            // without a gap, unless an urgent message is waiting to go
+            // first. Then whatever else queued up during the gap goes next.
+            if self.urgent_tx.is_none() {
+                self.tx_gap_next.take().map(|appid| {
+                    let _ = self.apps.enter(appid, |app, upcalls| {
+                        self.send_queued(appid, app, upcalls);
+                    });
+                });
+            }
+            if self.tx_in_progress.is_none() && !self.send_kernel() {
+                self.send_pending();
+            }
+            self.finish_line();
+        }
+
+        self.arm_alarm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_robin_resumes_after_last_served() {
+        let apps = [1, 2, 3];
+        let order = || round_robin(|| apps.iter().copied(), Some(2));
+        assert!(order().eq([3, 1, 2]));
+        assert!(round_robin(|| apps.iter().copied(), None).eq([1, 2, 3]));
+        // An app that has gone away restarts the scan from the top.
+        assert!(round_robin(|| apps.iter().copied(), Some(7)).eq([1, 2, 3]));
+    }
+
+    #[test]
+    fn round_robin_interleaves_three_writers() {
+        // Three apps that always have a write pending are served in turn.
+        let apps = [1, 2, 3];
+        let mut last = None;
+        let mut served = [0; 6];
+        for slot in served.iter_mut() {
+            *slot = round_robin(|| apps.iter().copied(), last).next().unwrap();
+            last = Some(*slot);
+        }
+        assert_eq!(served, [1, 2, 3, 1, 2, 3]);
+    }
+
+    impl WriteSegment for [u8] {
+        fn segment_len(&self) -> usize {
+            self.len()
+        }
+
+        fn enter_data(&self, fun: &mut dyn FnMut(&ReadableProcessSlice)) {
+            fun(self.into())
+        }
+    }
+
+    /// Builds a write laid out as `format` from `segments` through a
+    /// transmit buffer of `buf_len` bytes, one transaction after the other
+    /// as `transmitted_buffer` continues it, returning each transaction.
+    fn chunk_write(segments: &[&[u8]], format: WriteFormat, buf_len: usize) -> [[u8; 8]; 8] {
+        let mut transactions = [[0; 8]; 8];
+        let mut sent = 0;
+        for transaction in transactions.iter_mut() {
+            let end = sent + cmp::min(format.len - sent, buf_len);
+            sent += build_chunk(transaction, segments.iter().copied(), format, sent, end);
+        }
+        transactions
+    }
+
+    /// The format of a plain write of `len` bytes.
+    fn plain(len: usize) -> WriteFormat {
+        WriteFormat {
+            len: len,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn writes_are_split_at_the_buffer_length() {
+        let t = chunk_write(&[b"abcdefghij"], plain(10), 4);
+        assert_eq!(&t[0][..4], b"abcd");
+        assert_eq!(&t[1][..4], b"efgh");
+        assert_eq!(&t[2][..3], b"ij\0");
+        assert_eq!(t[3], [0; 8]);
+    }
+
+    #[test]
+    fn chunks_continue_across_write_segments() {
+        // Segment boundaries fall inside, at the start of, and at the end of
+        // transactions; the empty segment contributes nothing.
+        let t = chunk_write(&[b"ab", b"", b"cdefg", b"hi"], plain(9), 3);
+        assert_eq!(&t[0][..4], b"abc\0");
+        assert_eq!(&t[1][..4], b"def\0");
+        assert_eq!(&t[2][..4], b"ghi\0");
+        assert_eq!(t[3], [0; 8]);
+        assert_eq!(segment_span(5, 5, 0, 3), None);
+        assert_eq!(segment_span(5, 5, 7, 20), Some((2, 3)));
+    }
+
+    #[test]
+    fn chunks_stop_short_when_a_segment_shrank() {
+        // The write was started for 6 bytes, but only 4 are left.
+        let t = chunk_write(&[b"ab", b"cd"], plain(6), 8);
+        assert_eq!(&t[0][..5], b"abcd\0");
+    }
+
+    #[test]
+    fn hex_chunks_split_within_pairs() {
+        let format = WriteFormat {
+            len: 8,
+            hex: Some(3),
+            ..Default::default()
+        };
+        let t = chunk_write(&[b"\x01\xab", b"\xff"], format, 3);
+        assert_eq!(&t[0][..3], b"01 ");
+        assert_eq!(&t[1][..3], b"ab ");
+        assert_eq!(&t[2][..3], b"ff\0");
+        let t = chunk_write(&[b"\x01\xab", b"\xff"], format, 5);
+        assert_eq!(&t[0][..5], b"01 ab");
+        assert_eq!(&t[1][..4], b" ff\0");
+    }
+
+    #[test]
+    fn appended_newline_follows_the_last_app_byte() {
+        let format = WriteFormat {
+            len: 4,
+            newline: true,
+            ..Default::default()
+        };
+        let t = chunk_write(&[b"abc"], format, 2);
+        assert_eq!(&t[0][..2], b"ab");
+        assert_eq!(&t[1][..3], b"c\n\0");
+        // The newline can be a transaction of its own, after hex digits.
+        let format = WriteFormat {
+            len: 3,
+            hex: Some(2),
+            newline: true,
+            ..Default::default()
+        };
+        let t = chunk_write(&[b"\x3a"], format, 2);
+        assert_eq!(&t[0][..2], b"3a");
+        assert_eq!(&t[1][..2], b"\n\0");
+    }
+
+    #[test]
+    fn fill_writes_need_no_segments() {
+        let format = WriteFormat {
+            len: 5,
+            fill: Some(b'x'),
+            ..Default::default()
+        };
+        let t = chunk_write(&[], format, 2);
+        assert_eq!(&t[0][..3], b"xx\0");
+        assert_eq!(&t[1][..3], b"xx\0");
+        assert_eq!(&t[2][..2], b"x\0");
+        assert_eq!(t[3], [0; 8]);
+    }
+
+    #[test]
+    fn hex_dumps_count_digits_and_separators() {
+        let hex = |byte| [hex_char(byte, 0), hex_char(byte, 1), hex_char(byte, 2)];
+        assert_eq!(&hex(0x3a), b"3a ");
+        assert_eq!(&hex(0xf0), b"f0 ");
+        let mut app = App::default();
+        app.write_hex = Some(2);
+        assert_eq!(app.output_len(4), 8);
+        app.write_hex = Some(3);
+        assert_eq!(app.output_len(4), 11);
+        assert_eq!(app.output_len(0), 0);
+        app.write_newline = true;
+        assert_eq!(app.output_len(4), 12);
+    }
+
+    #[test]
+    fn rx_abort_reports_whether_a_read_was_stopped() {
+        // Active receive, cancelled by the UART.
+        assert_eq!(rx_abort_result(Some(Err(ErrorCode::BUSY))), Ok(()));
+        // Active receive the UART could not cut short.
+        assert_eq!(
+            rx_abort_result(Some(Err(ErrorCode::FAIL))),
+            Err(ErrorCode::FAIL)
+        );
+        // No receive to abort.
+        assert_eq!(rx_abort_result(None), Err(ErrorCode::ALREADY));
+        assert_eq!(rx_abort_result(Some(Ok(()))), Err(ErrorCode::ALREADY));
+    }
+
+    #[test]
+    fn rx_errors_have_distinct_codes() {
+        let errors = [
+            uart::Error::None,
+            uart::Error::ParityError,
+            uart::Error::FramingError,
+            uart::Error::OverrunError,
+            uart::Error::RepeatCallError,
+            uart::Error::ResetError,
+            uart::Error::Aborted,
+            uart::Error::BreakError,
+        ];
+        for (i, a) in errors.iter().enumerate() {
+            for b in &errors[i + 1..] {
+                assert_ne!(rx_error_report(*a).0, rx_error_report(*b).0);
+            }
+        }
+        assert_eq!(rx_error_report(uart::Error::None), (Ok(()), 0));
+    }
+
+    #[test]
+    fn baud_rate_zero_is_rejected() {
+        assert_eq!(baud_rate_result(0, Ok(0)), Err(ErrorCode::INVAL));
+        assert_eq!(
+            baud_rate_result(0, Err(ErrorCode::NOSUPPORT)),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(baud_rate_result(9600, Ok(9598)), Ok(9598));
+        assert_eq!(baud_rate_result(9600, Ok(10000)), Err(ErrorCode::INVAL));
+        assert_eq!(baud_rate_result(9600, Err(ErrorCode::NOSUPPORT)), Ok(9600));
+    }
+
+    #[test]
+    fn write_buffers_are_lent_until_the_write_completes() {
+        let mut app = App::default();
+        assert!(!app.write_uses(0));
+        // A gather write over the first two buffers.
+        app.write_len = 5;
+        app.write_segments = 2;
+        assert!(app.write_uses(0));
+        assert!(app.write_uses(1));
+        assert!(!app.write_uses(2));
+        // Once it completes, every buffer can be replaced again.
+        app.write_len = 0;
+        assert!(!app.write_uses(0));
+    }
+
+    #[test]
+    fn short_transmits_requeue_what_was_not_sent() {
+        let chunk = b"abcdef";
+        let fresh = || {
+            let mut app = App::default();
+            // A 10-byte write with a 6-byte transaction in flight.
+            app.write_len = 10;
+            app.write_remaining = 4;
+            app.tx_chunk_len = chunk.len();
+            app.tx_chunk_crc = 0x1234;
+            app.crc_poly = Some(DEFAULT_CRC_POLY);
+            app
+        };
+
+        // Partial: the last four bytes go out again.
+        let mut app = fresh();
+        let unsent = app.unsent(2);
+        assert_eq!(unsent, 4);
+        app.requeue_unsent(unsent, chunk);
+        assert_eq!(app.write_remaining, 8);
+        assert_eq!(app.tx_tokens, 4);
+        assert_eq!(
+            app.tx_crc,
+            crc16(DEFAULT_CRC_POLY, 0x1234, b"ab".iter().copied())
+        );
+
+        // Zero-length: the whole transaction goes out again.
+        let mut app = fresh();
+        let unsent = app.unsent(0);
+        assert_eq!(unsent, chunk.len());
+        app.requeue_unsent(unsent, chunk);
+        assert_eq!(app.write_remaining, 10);
+        assert_eq!(app.tx_crc, 0x1234);
+
+        // Full: nothing to requeue, and the CRC is left alone.
+        let mut app = fresh();
+        app.tx_crc = 0x5678;
+        let unsent = app.unsent(chunk.len());
+        assert_eq!(unsent, 0);
+        app.requeue_unsent(unsent, chunk);
+        assert_eq!(app.write_remaining, 4);
+        assert_eq!(app.tx_tokens, 0);
+        assert_eq!(app.tx_crc, 0x5678);
+    }
+
+    #[test]
+    fn writes_are_trimmed_to_the_allowed_buffer() {
+        let fresh = || {
+            let mut app = App::default();
+            // A 10-byte write, 4 bytes of which are already out.
+            app.write_len = 10;
+            app.write_remaining = 6;
+            app
+        };
+
+        // The buffer still holds the whole write.
+        let mut app = fresh();
+        app.clamp_write(10);
+        assert_eq!((app.write_len, app.write_remaining), (10, 6));
+
+        // The buffer shrank: only what it still holds is sent.
+        let mut app = fresh();
+        app.clamp_write(7);
+        assert_eq!((app.write_len, app.write_remaining), (7, 3));
+
+        // The buffer was revoked: the write ends with what was sent.
+        let mut app = fresh();
+        app.clamp_write(0);
+        assert_eq!((app.write_len, app.write_remaining), (4, 0));
+    }
+
+    #[test]
+    fn failed_transmits_report_only_what_went_out() {
+        let mut app = App::default();
+        // 4 of 10 bytes are out and 3 more are in flight when the UART
+        // fails, having sent only 1 of them.
+        app.write_len = 10;
+        app.write_remaining = 3;
+        app.tx_chunk_len = 3;
+        app.tx_crc_pending = true;
+        let unsent = app.unsent(1);
+        app.truncate_write(unsent);
+        assert_eq!((app.write_len, app.write_remaining), (5, 0));
+        assert!(!app.tx_crc_pending);
+    }
+
+    #[test]
+    fn rejected_transmits_are_undone() {
+        let mut app = App::default();
+        app.write_len = 10;
+        app.write_remaining = 10;
+        app.tx_tokens = 4;
+        app.tx_crc = 0x1234;
+        app.tx_crc_pending = true;
+        let before = (app.write_remaining, app.tx_tokens, app.tx_crc);
+
+        // A payload transaction the UART refuses to take.
+        let tokens = app.begin_transaction(6, 6, 0x5678, false);
+        assert_eq!(
+            (app.write_remaining, app.tx_tokens, app.tx_crc),
+            (4, 0, 0x5678)
+        );
+        app.unwind_transaction(false, tokens);
+        assert_eq!((app.write_remaining, app.tx_tokens, app.tx_crc), before);
+        assert!(app.tx_crc_pending);
+
+        // The CRC trailer is owed again if its transaction is refused.
+        app.write_remaining = 0;
+        let tokens = app.begin_transaction(CRC_LEN, 0, 0x1234, true);
+        assert!(!app.tx_crc_pending);
+        app.unwind_transaction(true, tokens);
+        assert!(app.tx_crc_pending);
+        assert_eq!((app.write_remaining, app.tx_tokens), (0, 4));
+    }
+
+    #[test]
+    fn echo_goes_out_between_the_transactions_of_a_write() {
+        use TxNext::*;
+        // Echoed bytes wait for the current transaction only.
+        assert_eq!(next_transaction(true, false, false, true), YieldToEcho);
+        assert_eq!(next_transaction(true, false, false, false), Continue);
+        // A finished write has nothing to interleave with.
+        assert_eq!(next_transaction(false, false, true, true), Continue);
+        // An atomic write keeps the line until it is done.
+        assert_eq!(next_transaction(true, true, true, true), Continue);
+        // A higher-priority app goes before the echo.
+        assert_eq!(next_transaction(true, false, true, true), YieldToPriority);
+    }
+
+    #[test]
+    fn char_time_counts_every_bit_of_the_frame() {
+        let params = uart::Parameters {
+            baud_rate: 115200,
+            width: uart::Width::Eight,
+            parity: uart::Parity::None,
+            stop_bits: uart::StopBits::One,
+            hw_flow_control: false,
+        };
+        // 10 bits at 115200 baud is 86.8 us.
+        assert_eq!(char_time_us(params), 87);
+        let params = uart::Parameters {
+            baud_rate: 9600,
+            parity: uart::Parity::Even,
+            stop_bits: uart::StopBits::Two,
+            ..params
+        };
+        // 12 bits at 9600 baud is exactly 1250 us.
+        assert_eq!(char_time_us(params), 1250);
+    }
+
+    #[test]
+    fn crc16_xmodem_check_value() {
+        assert_eq!(
+            crc16(DEFAULT_CRC_POLY, 0, b"123456789".iter().copied()),
+            0x31C3
+        );
+    }
+
+    #[test]
+    fn crc16_continues_across_chunks() {
+        let whole = crc16(DEFAULT_CRC_POLY, 0, b"123456789".iter().copied());
+        let first = crc16(DEFAULT_CRC_POLY, 0, b"1234".iter().copied());
+        assert_eq!(
+            crc16(DEFAULT_CRC_POLY, first, b"56789".iter().copied()),
+            whole
+        );
+    }
+
+    #[test]
+    fn rx_ring_wraps_around() {
+        let ring = RxRing::new();
+        for i in 0..RX_RING_LEN - 1 {
+            ring.push(i as u8);
+            assert_eq!(ring.pop(), Some(i as u8));
+        }
+        // The head is now at the last slot: these two straddle the end.
+        ring.push(1);
+        ring.push(2);
+        assert_eq!(ring.pop(), Some(1));
+        assert_eq!(ring.pop(), Some(2));
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test]
+    fn rx_ring_counts_dropped_bytes() {
+        let ring = RxRing::new();
+        for i in 0..RX_RING_LEN + 3 {
+            ring.push(i as u8);
+        }
+        assert_eq!(ring.take_dropped(), 3);
+        assert_eq!(ring.take_dropped(), 0);
+        // The oldest bytes are kept, the newest dropped.
+        assert_eq!(ring.pop(), Some(0));
+        assert!(core::iter::from_fn(|| ring.pop()).eq(1..RX_RING_LEN as u8));
     }
 }
@@ -96,3 +96,6 @@ pub mod virtual_sha;
 pub mod virtual_spi;
 pub mod virtual_timer;
 pub mod virtual_uart;
+pub mod watchdog_checkin;
+pub mod watchdog_heartbeat;
+pub mod watchdog_notify;
@@ -41,7 +41,11 @@
 //! ```rust
 //! pub struct Platform {
 //!     // Other fields omitted for clarity
-//!     console: &'static capsules::console::Console<'static>,
+//!     console: &'static capsules::console::Console<
+//!         'static,
+//!         capsules::segger_rtt::SeggerRtt<'static, VirtualMuxAlarm<'static, nrf5x::rtc::Rtc<'static>>>,
+//!         VirtualMuxAlarm<'static, nrf5x::rtc::Rtc<'static>>,
+//!     >,
 //! }
 //! ```
 //!
@@ -73,16 +77,29 @@
 //! );
 //! virtual_alarm_rtt.set_client(rtt);
 //!
+//! let console_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf5x::rtc::Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! console_alarm.setup();
+//!
 //! let console = static_init!(
-//!     capsules::console::Console<'static>,
+//!     capsules::console::Console<
+//!         'static,
+//!         capsules::segger_rtt::SeggerRtt<'static, VirtualMuxAlarm<'static, nrf5x::rtc::Rtc<'static>>>,
+//!         VirtualMuxAlarm<'static, nrf5x::rtc::Rtc<'static>>,
+//!     >,
 //!     capsules::console::Console::new(
 //!         rtt,
+//!         console_alarm,
+//!         115200, // RTT has no baud rate; this is only reported to apps.
 //!         &mut capsules::console::WRITE_BUF,
 //!         &mut capsules::console::READ_BUF,
-//!         board_kernel.create_grant(&grant_cap)
+//!         board_kernel.create_grant(capsules::console::DRIVER_NUM, &grant_cap)
 //!     )
 //! );
 //! kernel::hil::uart::UART::set_client(rtt, console);
+//! console_alarm.set_alarm_client(console);
 //! console.initialize();
 //! ```
 
@@ -15,6 +15,7 @@
 //!
 //! ```rust
 //! # use kernel::{hil, static_init};
+//! # use capsules::virtual_alarm::VirtualMuxAlarm;
 //! # use capsules::virtual_uart::{MuxUart, UartDevice};
 //!
 //! // Create a shared UART channel for the console and for kernel debug.
@@ -28,17 +29,29 @@
 //! // Create a UartDevice for the console.
 //! let console_uart = static_init!(UartDevice, UartDevice::new(uart_mux, true));
 //! console_uart.setup(); // This is important!
+//! let console_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! console_alarm.setup();
 //! let console = static_init!(
-//!     capsules::console::Console<'static>,
+//!     capsules::console::Console<
+//!         'static,
+//!         UartDevice<'static>,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     >,
 //!     capsules::console::Console::new(
 //!         console_uart,
+//!         console_alarm,
+//!         uart_mux.speed(),
 //!         &mut capsules::console::WRITE_BUF,
 //!         &mut capsules::console::READ_BUF,
-//!         board_kernel.create_grant(&grant_cap)
+//!         board_kernel.create_grant(capsules::console::DRIVER_NUM, &grant_cap)
 //!     )
 //! );
-//! hil::uart::UART::set_transmit_client(console_uart, console);
-//! hil::uart::UART::set_receive_client(console_uart, console);
+//! hil::uart::Transmit::set_transmit_client(console_uart, console);
+//! hil::uart::Receive::set_receive_client(console_uart, console);
+//! console_alarm.set_alarm_client(console);
 //! ```
 
 use core::cell::Cell;
@@ -218,13 +231,27 @@ impl<'a> MuxUart<'a> {
         });
     }
 
+    /// Baud rate the UART is configured with by `initialize`.
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
     pub fn initialize_callback_handle(&self, handle: DeferredCallHandle) {
         self.handle.replace(handle);
     }
 
     fn do_next_op(&self) {
         if self.inflight.is_none() {
-            let mnode = self.devices.iter().find(|node| node.operation.is_some());
+            // Pick the pending device with the highest priority. Devices of
+            // equal priority are served in list order.
+            let mnode = self
+                .devices
+                .iter()
+                .filter(|node| node.operation.is_some())
+                .fold(None, |best: Option<&UartDevice<'a>>, node| match best {
+                    Some(b) if b.priority.get() >= node.priority.get() => Some(b),
+                    _ => Some(node),
+                });
             mnode.map(|node| {
                 node.tx_buffer.take().map(|buf| {
                     node.operation.map(move |op| match op {
@@ -327,6 +354,7 @@ pub struct UartDevice<'a> {
     state: Cell<UartDeviceReceiveState>,
     mux: &'a MuxUart<'a>,
     receiver: bool, // Whether or not to pass this UartDevice incoming messages.
+    priority: Cell<u8>,
     tx_buffer: TakeCell<'static, [u8]>,
     transmitting: Cell<bool>,
     rx_buffer: TakeCell<'static, [u8]>,
@@ -344,6 +372,7 @@ impl<'a> UartDevice<'a> {
             state: Cell::new(UartDeviceReceiveState::Idle),
             mux: mux,
             receiver: receiver,
+            priority: Cell::new(0),
             tx_buffer: TakeCell::empty(),
             transmitting: Cell::new(false),
             rx_buffer: TakeCell::empty(),
@@ -360,6 +389,15 @@ impl<'a> UartDevice<'a> {
     pub fn setup(&'a self) {
         self.mux.devices.push_head(self);
     }
+
+    /// Set the transmit priority of this device. When the mux is free to
+    /// start a new transmission it serves the pending device with the highest
+    /// priority, so a higher priority device preempts others at transaction
+    /// boundaries. An in-flight transmission is never interrupted. Defaults
+    /// to 0.
+    pub fn set_priority(&self, priority: u8) {
+        self.priority.set(priority);
+    }
 }
 
 impl<'a> uart::TransmitClient for UartDevice<'a> {
@@ -441,6 +479,30 @@ impl<'a> uart::Transmit<'a> for UartDevice<'a> {
     }
 }
 
+impl<'a> uart::Configure for UartDevice<'a> {
+    /// Reconfigures the underlying UART. Note that this affects every device
+    /// sharing the mux, not just this one.
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.mux.uart.configure(params)
+    }
+
+    fn nearest_baud_rate(&self, baud_rate: u32) -> Result<u32, ErrorCode> {
+        self.mux.uart.nearest_baud_rate(baud_rate)
+    }
+
+    /// Address filtering applies to the underlying UART, so every receiving
+    /// device on the mux sees only the matched frames.
+    fn set_address_match(&self, address: Option<u8>) -> Result<(), ErrorCode> {
+        self.mux.uart.set_address_match(address)
+    }
+
+    /// Loopback applies to the underlying UART, so output from every device
+    /// on the mux is looped back while it is on.
+    fn set_loopback(&self, enabled: bool) -> Result<bool, ErrorCode> {
+        self.mux.uart.set_loopback(enabled)
+    }
+}
+
 impl<'a> uart::Receive<'a> for UartDevice<'a> {
     fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
         self.rx_client.set(client);
@@ -478,4 +540,14 @@ impl<'a> uart::Receive<'a> for UartDevice<'a> {
     fn receive_word(&self) -> Result<(), ErrorCode> {
         Err(ErrorCode::FAIL)
     }
+
+    /// Received data is shared by every device on the mux, so it can only
+    /// be discarded while none of them is receiving.
+    fn receive_flush(&self) -> Result<(), ErrorCode> {
+        if self.mux.buffer.is_none() {
+            Err(ErrorCode::BUSY)
+        } else {
+            self.mux.uart.receive_flush()
+        }
+    }
 }
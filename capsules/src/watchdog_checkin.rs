@@ -0,0 +1,176 @@
+//! Only services the watchdog once every monitored subsystem has checked in.
+//!
+//! `WatchdogCheckIn` wraps a platform watchdog and is itself a `WatchDog`, so
+//! it can take the watchdog's place in `KernelResources::watchdog()` or under
+//! a `watchdog_heartbeat::WatchdogHeartbeat`. Each monitored subsystem has an
+//! id below 32 and calls `check_in(id)` whenever it has made progress. A
+//! tickle only reaches the watchdog once all of them have checked in since
+//! the last one that did, which starts a new window. If any subsystem hangs,
+//! the tickles stop reaching the watchdog and it expires, even though the
+//! kernel loop is otherwise healthy.
+//!
+//! The watchdog is only suspended around sleep while the last tickle reached
+//! it. Many watchdogs restart their count on resume, and the kernel sleeps
+//! between most loop iterations, so passing every resume through would keep
+//! servicing the watchdog however long a subsystem has been hung. Once a
+//! tickle has been held back, the watchdog keeps counting through sleep
+//! until the missing subsystems check in. Servicing it from the panic handler
+//! passes straight through, so that printing a panic does not reset the chip.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! const RADIO: usize = 0;
+//! const SENSORS: usize = 1;
+//!
+//! let watchdog = static_init!(
+//!     capsules::watchdog_checkin::WatchdogCheckIn<'static, sam4l::wdt::Wdt>,
+//!     capsules::watchdog_checkin::WatchdogCheckIn::new(
+//!         &peripherals.wdt,
+//!         1 << RADIO | 1 << SENSORS
+//!     )
+//! );
+//! // Later, from each subsystem:
+//! watchdog.check_in(RADIO);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::platform::watchdog::WatchDog;
+
+pub struct WatchdogCheckIn<'a, W: WatchDog> {
+    watchdog: &'a W,
+    // Bit `id` is set for every subsystem that must check in.
+    expected: u32,
+    // Subsystems that have checked in during the current window.
+    checked_in: Cell<u32>,
+    // Whether the last tickle reached the watchdog.
+    serviced: Cell<bool>,
+    // Whether the watchdog was suspended for the current sleep.
+    suspended: Cell<bool>,
+}
+
+impl<'a, W: WatchDog> WatchdogCheckIn<'a, W> {
+    /// Create a coordinator that services `watchdog` only once every
+    /// subsystem with its bit set in `expected` has checked in.
+    pub fn new(watchdog: &'a W, expected: u32) -> WatchdogCheckIn<'a, W> {
+        WatchdogCheckIn {
+            watchdog: watchdog,
+            expected: expected,
+            checked_in: Cell::new(0),
+            serviced: Cell::new(false),
+            suspended: Cell::new(false),
+        }
+    }
+
+    /// Record that subsystem `id` has made progress in the current window.
+    /// Ids that are not monitored are ignored.
+    pub fn check_in(&self, id: usize) {
+        if id < 32 {
+            let bit = (1 << id) & self.expected;
+            self.checked_in.set(self.checked_in.get() | bit);
+        }
+    }
+}
+
+impl<'a, W: WatchDog> WatchDog for WatchdogCheckIn<'a, W> {
+    fn setup(&self) {
+        self.watchdog.setup();
+    }
+
+    fn tickle(&self) {
+        let serviced = self.checked_in.get() == self.expected;
+        self.serviced.set(serviced);
+        if serviced {
+            self.checked_in.set(0);
+            self.watchdog.tickle();
+        }
+    }
+
+    fn suspend(&self) {
+        if self.serviced.get() {
+            self.suspended.set(true);
+            self.watchdog.suspend();
+        }
+    }
+
+    fn resume(&self) {
+        if self.suspended.replace(false) {
+            self.watchdog.resume();
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        self.watchdog.is_running()
+    }
+
+    fn tickle_from_panic(&self) {
+        self.watchdog.tickle_from_panic();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Like the sam4l and msp432 watchdogs, resuming tickles it.
+    #[derive(Default)]
+    struct CountingWatchDog {
+        tickles: Cell<usize>,
+        suspends: Cell<usize>,
+    }
+
+    impl WatchDog for CountingWatchDog {
+        fn tickle(&self) {
+            self.tickles.set(self.tickles.get() + 1);
+        }
+
+        fn suspend(&self) {
+            self.suspends.set(self.suspends.get() + 1);
+        }
+    }
+
+    #[test]
+    fn tickles_only_once_all_subsystems_checked_in() {
+        let wdt = CountingWatchDog::default();
+        let coordinator = WatchdogCheckIn::new(&wdt, 0b101);
+        coordinator.tickle();
+        assert_eq!(wdt.tickles.get(), 0);
+        coordinator.check_in(0);
+        coordinator.check_in(1); // Not monitored.
+        coordinator.check_in(40); // Out of range.
+        coordinator.tickle();
+        assert_eq!(wdt.tickles.get(), 0);
+        coordinator.check_in(2);
+        coordinator.tickle();
+        assert_eq!(wdt.tickles.get(), 1);
+        // A new window starts after every successful service.
+        coordinator.tickle();
+        assert_eq!(wdt.tickles.get(), 1);
+    }
+
+    #[test]
+    fn sleeping_does_not_service_a_held_back_watchdog() {
+        let wdt = CountingWatchDog::default();
+        let coordinator = WatchdogCheckIn::new(&wdt, 0b1);
+        // Nobody has checked in: the watchdog keeps counting through sleep.
+        coordinator.tickle();
+        coordinator.suspend();
+        coordinator.resume();
+        assert_eq!((wdt.tickles.get(), wdt.suspends.get()), (0, 0));
+        // Once a tickle gets through, sleep suspends and resumes it again.
+        coordinator.check_in(0);
+        coordinator.tickle();
+        coordinator.suspend();
+        coordinator.resume();
+        assert_eq!((wdt.tickles.get(), wdt.suspends.get()), (2, 1));
+        // Until the next tickle is held back.
+        coordinator.tickle();
+        coordinator.suspend();
+        coordinator.resume();
+        assert_eq!((wdt.tickles.get(), wdt.suspends.get()), (2, 1));
+    }
+}
@@ -0,0 +1,254 @@
+//! Services the watchdog from a periodic alarm rather than the kernel loop.
+//!
+//! The kernel normally tickles the watchdog at the top of every main loop
+//! iteration, which ties servicing the watchdog to the scheduler. This
+//! capsule instead tickles it from its own alarm, every half of the watchdog
+//! timeout, for as long as an optional liveness check says the system is
+//! healthy. Once the check fails the heartbeat stops for good and the
+//! watchdog is left to expire, so it is the check, rather than the loop
+//! merely turning, that decides whether the chip is reset.
+//!
+//! The kernel loop must not tickle the same watchdog, or it would keep the
+//! chip alive regardless: the board's `KernelResources::watchdog()` returns
+//! `()` and the board starts the heartbeat itself. The kernel also no longer
+//! suspends the watchdog while the chip sleeps; the alarm wakes the chip to
+//! keep it serviced.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! let heartbeat_alarm = static_init!(
+//!     capsules::virtual_alarm::VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! heartbeat_alarm.setup();
+//! let heartbeat = static_init!(
+//!     capsules::watchdog_heartbeat::WatchdogHeartbeat<
+//!         'static,
+//!         sam4l::wdt::Wdt,
+//!         capsules::virtual_alarm::VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     >,
+//!     capsules::watchdog_heartbeat::WatchdogHeartbeat::new(
+//!         &peripherals.wdt,
+//!         heartbeat_alarm,
+//!         1000, // The watchdog timeout in milliseconds.
+//!     )
+//! );
+//! heartbeat_alarm.set_alarm_client(heartbeat);
+//! heartbeat.start();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::platform::watchdog::WatchDog;
+use kernel::utilities::cells::OptionalCell;
+
+/// Check the heartbeat makes before each tickle.
+pub trait Liveness {
+    /// Return whether the system is still healthy. Returning `false` stops
+    /// the heartbeat, and the watchdog resets the chip once it expires.
+    fn is_alive(&self) -> bool;
+}
+
+pub struct WatchdogHeartbeat<'a, W: WatchDog, A: Alarm<'a>> {
+    watchdog: &'a W,
+    alarm: &'a A,
+    // Time between tickles, half the watchdog timeout.
+    period_ms: u32,
+    liveness: OptionalCell<&'a dyn Liveness>,
+    // Set once the liveness check has failed.
+    stopped: Cell<bool>,
+}
+
+impl<'a, W: WatchDog, A: Alarm<'a>> WatchdogHeartbeat<'a, W, A> {
+    /// Create a heartbeat for a watchdog that expires `timeout_ms`
+    /// milliseconds after it was last tickled.
+    pub fn new(watchdog: &'a W, alarm: &'a A, timeout_ms: u32) -> WatchdogHeartbeat<'a, W, A> {
+        WatchdogHeartbeat {
+            watchdog: watchdog,
+            alarm: alarm,
+            period_ms: core::cmp::max(timeout_ms / 2, 1),
+            liveness: OptionalCell::empty(),
+            stopped: Cell::new(false),
+        }
+    }
+
+    /// Set the check that must pass before each tickle.
+    pub fn set_liveness(&self, liveness: &'a dyn Liveness) {
+        self.liveness.set(liveness);
+    }
+
+    /// Start the watchdog and the heartbeat servicing it.
+    pub fn start(&self) {
+        self.watchdog.setup();
+        self.arm();
+    }
+
+    fn arm(&self) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+    }
+}
+
+impl<'a, W: WatchDog, A: Alarm<'a>> AlarmClient for WatchdogHeartbeat<'a, W, A> {
+    fn alarm(&self) {
+        if self.stopped.get() {
+            return;
+        }
+        if !self.liveness.map_or(true, |liveness| liveness.is_alive()) {
+            // Let the watchdog expire.
+            self.stopped.set(true);
+            return;
+        }
+        self.watchdog.tickle();
+        self.arm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::{Freq1KHz, Ticks, Ticks32, Time};
+    use kernel::ErrorCode;
+
+    #[derive(Default)]
+    struct CountingWatchDog {
+        setups: Cell<usize>,
+        tickles: Cell<usize>,
+    }
+
+    impl WatchDog for CountingWatchDog {
+        fn setup(&self) {
+            self.setups.set(self.setups.get() + 1);
+        }
+
+        fn tickle(&self) {
+            self.tickles.set(self.tickles.get() + 1);
+        }
+    }
+
+    /// Records when it was armed; tests fire it by calling the client.
+    #[derive(Default)]
+    struct FakeAlarm {
+        dt: Cell<u32>,
+        armed: Cell<bool>,
+    }
+
+    impl FakeAlarm {
+        /// Disarms the alarm, as firing does, and returns whether it was
+        /// armed.
+        fn fire(&self) -> bool {
+            self.armed.replace(false)
+        }
+    }
+
+    impl Time for FakeAlarm {
+        type Ticks = Ticks32;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> Ticks32 {
+            0u32.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for FakeAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Self::Ticks, dt: Self::Ticks) {
+            self.dt.set(dt.into_u32());
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            self.dt.get().into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            0u32.into()
+        }
+    }
+
+    struct FakeLiveness {
+        alive: Cell<bool>,
+    }
+
+    impl Liveness for FakeLiveness {
+        fn is_alive(&self) -> bool {
+            self.alive.get()
+        }
+    }
+
+    #[test]
+    fn tickles_every_half_timeout_while_alive() {
+        let wdt = CountingWatchDog::default();
+        let alarm = FakeAlarm::default();
+        let liveness = FakeLiveness {
+            alive: Cell::new(true),
+        };
+        let heartbeat = WatchdogHeartbeat::new(&wdt, &alarm, 1000);
+        heartbeat.set_liveness(&liveness);
+        heartbeat.start();
+        assert_eq!((wdt.setups.get(), wdt.tickles.get()), (1, 0));
+        assert_eq!(alarm.dt.get(), 500);
+        for tickles in 1..4 {
+            assert!(alarm.fire());
+            heartbeat.alarm();
+            assert_eq!(wdt.tickles.get(), tickles);
+        }
+        assert!(alarm.is_armed());
+    }
+
+    #[test]
+    fn stops_for_good_once_not_alive() {
+        let wdt = CountingWatchDog::default();
+        let alarm = FakeAlarm::default();
+        let liveness = FakeLiveness {
+            alive: Cell::new(true),
+        };
+        let heartbeat = WatchdogHeartbeat::new(&wdt, &alarm, 1000);
+        heartbeat.set_liveness(&liveness);
+        heartbeat.start();
+        alarm.fire();
+        heartbeat.alarm();
+        assert_eq!(wdt.tickles.get(), 1);
+
+        // The tickle is held back and the heartbeat is not rearmed.
+        liveness.alive.set(false);
+        alarm.fire();
+        heartbeat.alarm();
+        assert_eq!(wdt.tickles.get(), 1);
+        assert!(!alarm.is_armed());
+
+        // Recovering does not revive it: the watchdog is left to expire.
+        liveness.alive.set(true);
+        heartbeat.alarm();
+        assert_eq!(wdt.tickles.get(), 1);
+        assert!(!alarm.is_armed());
+    }
+
+    #[test]
+    fn tickles_without_a_liveness_check() {
+        let wdt = CountingWatchDog::default();
+        let alarm = FakeAlarm::default();
+        let heartbeat = WatchdogHeartbeat::new(&wdt, &alarm, 10);
+        heartbeat.start();
+        assert_eq!(alarm.dt.get(), 5);
+        alarm.fire();
+        heartbeat.alarm();
+        assert_eq!(wdt.tickles.get(), 1);
+        assert!(alarm.is_armed());
+    }
+}
@@ -0,0 +1,171 @@
+//! Notifies a userspace app when the watchdog is about to expire.
+//!
+//! Only the app the board names as privileged can claim the notification, and
+//! only while no other instance of it holds it. Claiming it enables the
+//! watchdog's pre-timeout interrupt, and each time that interrupt fires the
+//! app's upcall is scheduled, giving it a last chance to save state.
+//!
+//! The kernel services the watchdog at the top of every main loop iteration,
+//! and the upcall is delivered from that same loop. A delivered notification
+//! therefore means the kernel came close to missing its deadline, not that a
+//! reset is guaranteed to follow.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! struct WatchdogNotifyCapability;
+//! unsafe impl capabilities::ProcessManagementCapability for WatchdogNotifyCapability {}
+//!
+//! let watchdog_notify = static_init!(
+//!     capsules::watchdog_notify::WatchdogNotify<
+//!         'static,
+//!         stm32f303xc::wdt::WindoWdg<'static>,
+//!         WatchdogNotifyCapability,
+//!     >,
+//!     capsules::watchdog_notify::WatchdogNotify::new(
+//!         &peripherals.watchdog,
+//!         board_kernel.create_grant(capsules::watchdog_notify::DRIVER_NUM, &memory_allocation_capability),
+//!         board_kernel,
+//!         "watchdog_notify",
+//!         WatchdogNotifyCapability,
+//!     )
+//! );
+//! kernel::hil::watchdog::PreTimeout::set_client(&peripherals.watchdog, watchdog_notify);
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::Grant;
+use kernel::hil::watchdog::{PreTimeout, PreTimeoutClient};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::WatchdogNotify as usize;
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct WatchdogNotify<'a, W: PreTimeout<'a>, C: ProcessManagementCapability> {
+    watchdog: &'a W,
+    apps: Grant<App, 1>,
+    // The app that receives pre-timeout notifications.
+    owner: OptionalCell<ProcessId>,
+    kernel: &'static Kernel,
+    // Process name of the only app allowed to claim the notification.
+    privileged_app: &'static str,
+    capability: C,
+}
+
+impl<'a, W: PreTimeout<'a>, C: ProcessManagementCapability> WatchdogNotify<'a, W, C> {
+    pub fn new(
+        watchdog: &'a W,
+        grant: Grant<App, 1>,
+        kernel: &'static Kernel,
+        privileged_app: &'static str,
+        capability: C,
+    ) -> WatchdogNotify<'a, W, C> {
+        WatchdogNotify {
+            watchdog: watchdog,
+            apps: grant,
+            owner: OptionalCell::empty(),
+            kernel: kernel,
+            privileged_app: privileged_app,
+            capability: capability,
+        }
+    }
+
+    fn claim(&self, appid: ProcessId) -> Result<(), ErrorCode> {
+        let privileged = self.kernel.process_map_or_external(
+            false,
+            appid,
+            |process| process.get_process_name() == self.privileged_app,
+            &self.capability,
+        );
+        if !privileged {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+
+        // Another app holds the notification for as long as it is alive.
+        let held_by_other = self.owner.map_or(false, |owner| {
+            *owner != appid && self.apps.enter(*owner, |_, _| {}).is_ok()
+        });
+        if held_by_other {
+            return Err(ErrorCode::RESERVE);
+        }
+
+        self.watchdog.enable_pre_timeout()?;
+        self.owner.set(appid);
+        Ok(())
+    }
+
+    fn release(&self, appid: ProcessId) -> Result<(), ErrorCode> {
+        if self.owner.contains(&appid) {
+            // The interrupt stays enabled, later notifications are dropped.
+            self.owner.clear();
+            Ok(())
+        } else {
+            Err(ErrorCode::RESERVE)
+        }
+    }
+}
+
+impl<'a, W: PreTimeout<'a>, C: ProcessManagementCapability> PreTimeoutClient
+    for WatchdogNotify<'a, W, C>
+{
+    fn pre_timeout(&self) {
+        self.owner.map(|owner| {
+            let _ = self.apps.enter(*owner, |_, upcalls| {
+                upcalls.schedule_upcall(0, (0, 0, 0)).ok();
+            });
+        });
+    }
+}
+
+impl<'a, W: PreTimeout<'a>, C: ProcessManagementCapability> SyscallDriver
+    for WatchdogNotify<'a, W, C>
+{
+    // Setup callbacks.
+    //
+    // ### `subscribe_num`
+    //
+    // - `0`: Pre-timeout callback. Only scheduled for the app that claimed
+    //   the notification with command `1`.
+
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Claim the pre-timeout notification and enable the watchdog's
+    ///   pre-timeout interrupt. Returns `NOSUPPORT` to apps other than the
+    ///   board's privileged one, `RESERVE` if another app holds it, or `OFF`
+    ///   if the watchdog is not in use.
+    /// - `2`: Release the pre-timeout notification. Returns `RESERVE` if the
+    ///   calling app does not hold it.
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        appid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.claim(appid).into(),
+
+            2 => self.release(appid).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,970 @@
+//! Runs the console against a mock UART and fake processes, and checks the
+//! order in which transmissions, receptions and upcalls happen.
+//!
+//! `MockUart` records every call the console makes and holds on to the
+//! buffers it is handed; a test then completes them with `transmit_done` and
+//! `receive`, which call the console's `transmitted_buffer` and
+//! `received_buffer` the way a UART interrupt would. `FakeProcess` provides
+//! just enough of `Process` for the console's grant and the app buffers to
+//! work, and records the upcalls scheduled for it in the same log.
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+use std::mem::size_of;
+use std::ptr::NonNull;
+
+use capsules::console::{self, Console};
+use kernel::capabilities::{ExternalProcessCapability, MemoryAllocationCapability};
+use kernel::hil::time::{Alarm, AlarmClient, Freq1KHz, Ticks32, Time};
+use kernel::hil::uart;
+use kernel::platform::mpu;
+use kernel::process::{
+    Error, FunctionCall, FunctionCallSource, Process, ProcessAddresses,
+    ProcessCustomGrantIdentifer, ProcessSizes, State, Task,
+};
+use kernel::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
+use kernel::syscall::{self, CommandReturn, Syscall, SyscallDriver, SyscallReturn};
+use kernel::upcall::UpcallId;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+struct TestCap;
+unsafe impl MemoryAllocationCapability for TestCap {}
+unsafe impl ExternalProcessCapability for TestCap {}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    /// The console started a transmission of these bytes.
+    Transmit(Vec<u8>),
+    /// The console started a reception of this many bytes.
+    Receive(usize),
+    /// The console configured the UART with this baud rate.
+    Configure(u32),
+    /// The console set the UART's multidrop address filter.
+    AddressMatch(Option<u8>),
+    /// An upcall to app `.0` with `subscribe_num` `.1` and arguments `.2`.
+    Upcall(usize, usize, (usize, usize, usize)),
+}
+
+type Log = &'static RefCell<Vec<Event>>;
+
+struct MockUart {
+    log: Log,
+    tx_client: OptionalCell<&'static dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'static dyn uart::ReceiveClient>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    /// Error to refuse the next transmission with.
+    tx_reject: Cell<Option<ErrorCode>>,
+    rx_buffer: TakeCell<'static, [u8]>,
+}
+
+impl MockUart {
+    fn new(log: Log) -> MockUart {
+        MockUart {
+            log,
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_reject: Cell::new(None),
+            rx_buffer: TakeCell::empty(),
+        }
+    }
+
+    /// Hands the transmission in flight back as fully sent.
+    fn transmit_done(&self) {
+        self.transmit_done_with(self.tx_len.get(), Ok(()));
+    }
+
+    /// Hands the transmission in flight back with `tx_len` bytes sent and
+    /// `rcode`.
+    fn transmit_done_with(&self, tx_len: usize, rcode: Result<(), ErrorCode>) {
+        let buffer = self.tx_buffer.take().expect("no transmission in flight");
+        self.tx_client
+            .map(|client| client.transmitted_buffer(buffer, tx_len, rcode));
+    }
+
+    /// Ends the reception in flight with `error`, having received nothing.
+    fn receive_failed(&self, error: uart::Error) {
+        let buffer = self.rx_buffer.take().expect("no reception in flight");
+        self.rx_client
+            .map(|client| client.received_buffer(buffer, 0, Err(ErrorCode::FAIL), error));
+    }
+
+    /// Completes the reception in flight with `data`.
+    fn receive(&self, data: &[u8]) {
+        let buffer = self.rx_buffer.take().expect("no reception in flight");
+        buffer[..data.len()].copy_from_slice(data);
+        self.rx_client
+            .map(|client| client.received_buffer(buffer, data.len(), Ok(()), uart::Error::None));
+    }
+}
+
+impl uart::Configure for MockUart {
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.log
+            .borrow_mut()
+            .push(Event::Configure(params.baud_rate));
+        Ok(())
+    }
+
+    fn set_address_match(&self, address: Option<u8>) -> Result<(), ErrorCode> {
+        self.log.borrow_mut().push(Event::AddressMatch(address));
+        Ok(())
+    }
+}
+
+impl uart::Transmit<'static> for MockUart {
+    fn set_transmit_client(&self, client: &'static dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        if let Some(ecode) = self.tx_reject.take() {
+            return Err((ecode, tx_buffer));
+        }
+        self.log
+            .borrow_mut()
+            .push(Event::Transmit(tx_buffer[..tx_len].to_vec()));
+        self.tx_len.set(tx_len);
+        self.tx_buffer.replace(tx_buffer);
+        Ok(())
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+}
+
+impl uart::Receive<'static> for MockUart {
+    fn set_receive_client(&self, client: &'static dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        self.log.borrow_mut().push(Event::Receive(rx_len));
+        self.rx_buffer.replace(rx_buffer);
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_some() {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An alarm that never fires on its own.
+struct FakeAlarm {
+    armed: Cell<bool>,
+    client: OptionalCell<&'static dyn AlarmClient>,
+}
+
+impl Time for FakeAlarm {
+    type Ticks = Ticks32;
+    type Frequency = Freq1KHz;
+
+    fn now(&self) -> Ticks32 {
+        0u32.into()
+    }
+}
+
+impl Alarm<'static> for FakeAlarm {
+    fn set_alarm_client(&self, client: &'static dyn AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn set_alarm(&self, _reference: Self::Ticks, _dt: Self::Ticks) {
+        self.armed.set(true);
+    }
+
+    fn get_alarm(&self) -> Self::Ticks {
+        0u32.into()
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.armed.set(false);
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+
+    fn minimum_dt(&self) -> Self::Ticks {
+        0u32.into()
+    }
+}
+
+/// A process with a single grant, the console's, and no memory of its own:
+/// its buffers are leaked host allocations.
+struct FakeProcess {
+    index: usize,
+    log: Log,
+    processid: Cell<Option<ProcessId>>,
+    alive: Cell<bool>,
+    grant: Cell<Option<NonNull<u8>>>,
+    grant_entered: Cell<bool>,
+}
+
+impl FakeProcess {
+    /// Subscribes to upcall `subscribe_num` of the console. `Grant` keeps
+    /// the upcalls at the start of the grant allocation: a `usize` count,
+    /// then an `(appdata, fn_ptr)` pair for each upcall. Any non-null
+    /// function pointer makes the upcall reach `enqueue_task`.
+    fn subscribe(&self, subscribe_num: usize) {
+        let grant = self.grant.get().expect("grant not allocated");
+        unsafe {
+            let upcalls = grant.as_ptr().add(size_of::<usize>()) as *mut [usize; 2];
+            *upcalls.add(subscribe_num) = [0, 0x1000];
+        }
+    }
+}
+
+impl Process for FakeProcess {
+    fn processid(&self) -> ProcessId {
+        self.processid.get().unwrap()
+    }
+
+    fn enqueue_task(&self, task: Task) -> Result<(), ErrorCode> {
+        if !self.alive.get() {
+            return Err(ErrorCode::NODEVICE);
+        }
+        if let Task::FunctionCall(FunctionCall {
+            source: FunctionCallSource::Driver(UpcallId { subscribe_num, .. }),
+            argument0,
+            argument1,
+            argument2,
+            ..
+        }) = task
+        {
+            self.log.borrow_mut().push(Event::Upcall(
+                self.index,
+                subscribe_num,
+                (argument0, argument1, argument2),
+            ));
+        }
+        Ok(())
+    }
+
+    fn ready(&self) -> bool {
+        false
+    }
+
+    fn has_tasks(&self) -> bool {
+        false
+    }
+
+    fn dequeue_task(&self) -> Option<Task> {
+        None
+    }
+
+    fn pending_tasks(&self) -> usize {
+        0
+    }
+
+    fn remove_pending_upcalls(&self, _upcall_id: UpcallId) {}
+
+    fn get_state(&self) -> State {
+        State::Yielded
+    }
+
+    fn set_yielded_state(&self) {}
+
+    fn stop(&self) {}
+
+    fn resume(&self) {}
+
+    fn set_fault_state(&self) {}
+
+    fn get_restart_count(&self) -> usize {
+        0
+    }
+
+    fn get_process_name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn terminate(&self, _completion_code: Option<u32>) {
+        self.alive.set(false);
+    }
+
+    fn try_restart(&self, _completion_code: Option<u32>) {}
+
+    fn brk(&self, _new_break: *const u8) -> Result<*const u8, Error> {
+        Err(Error::OutOfMemory)
+    }
+
+    fn sbrk(&self, _increment: isize) -> Result<*const u8, Error> {
+        Err(Error::OutOfMemory)
+    }
+
+    fn mem_start(&self) -> *const u8 {
+        unimplemented!()
+    }
+
+    fn mem_end(&self) -> *const u8 {
+        unimplemented!()
+    }
+
+    fn flash_start(&self) -> *const u8 {
+        unimplemented!()
+    }
+
+    fn flash_end(&self) -> *const u8 {
+        unimplemented!()
+    }
+
+    fn kernel_memory_break(&self) -> *const u8 {
+        unimplemented!()
+    }
+
+    fn number_writeable_flash_regions(&self) -> usize {
+        0
+    }
+
+    fn get_writeable_flash_region(&self, _region_index: usize) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn update_stack_start_pointer(&self, _stack_pointer: *const u8) {}
+
+    fn update_heap_start_pointer(&self, _heap_pointer: *const u8) {}
+
+    fn app_memory_break(&self) -> *const u8 {
+        unimplemented!()
+    }
+
+    fn build_readwrite_process_buffer(
+        &self,
+        _buf_start_addr: *mut u8,
+        _size: usize,
+    ) -> Result<ReadWriteProcessBuffer, ErrorCode> {
+        unimplemented!()
+    }
+
+    fn build_readonly_process_buffer(
+        &self,
+        _buf_start_addr: *const u8,
+        _size: usize,
+    ) -> Result<ReadOnlyProcessBuffer, ErrorCode> {
+        unimplemented!()
+    }
+
+    unsafe fn set_byte(&self, _addr: *mut u8, _value: u8) -> bool {
+        false
+    }
+
+    fn flash_non_protected_start(&self) -> *const u8 {
+        unimplemented!()
+    }
+
+    fn setup_mpu(&self) {}
+
+    fn add_mpu_region(
+        &self,
+        _unallocated_memory_start: *const u8,
+        _unallocated_memory_size: usize,
+        _min_region_size: usize,
+    ) -> Option<mpu::Region> {
+        None
+    }
+
+    fn remove_mpu_region(&self, _region: mpu::Region) -> Result<(), ErrorCode> {
+        Err(ErrorCode::INVAL)
+    }
+
+    fn allocate_grant(
+        &self,
+        grant_num: usize,
+        _driver_num: usize,
+        size: usize,
+        align: usize,
+    ) -> Option<NonNull<u8>> {
+        if !self.alive.get() || grant_num != 0 || self.grant.get().is_some() {
+            return None;
+        }
+        let layout = Layout::from_size_align(size, align).ok()?;
+        let grant = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })?;
+        self.grant.set(Some(grant));
+        Some(grant)
+    }
+
+    fn grant_is_allocated(&self, grant_num: usize) -> Option<bool> {
+        if !self.alive.get() {
+            return None;
+        }
+        Some(grant_num == 0 && self.grant.get().is_some())
+    }
+
+    fn allocate_custom_grant(
+        &self,
+        _size: usize,
+        _align: usize,
+    ) -> Option<(ProcessCustomGrantIdentifer, NonNull<u8>)> {
+        None
+    }
+
+    fn enter_grant(&self, grant_num: usize) -> Result<*mut u8, Error> {
+        if !self.alive.get() {
+            return Err(Error::NoSuchApp);
+        }
+        if grant_num != 0 {
+            return Err(Error::AddressOutOfBounds);
+        }
+        if self.grant_entered.replace(true) {
+            return Err(Error::AlreadyInUse);
+        }
+        self.grant
+            .get()
+            .map(|grant| grant.as_ptr())
+            .ok_or(Error::OutOfMemory)
+    }
+
+    fn enter_custom_grant(
+        &self,
+        _identifier: ProcessCustomGrantIdentifer,
+    ) -> Result<*mut u8, Error> {
+        Err(Error::AddressOutOfBounds)
+    }
+
+    fn leave_grant(&self, _grant_num: usize) {
+        self.grant_entered.set(false);
+    }
+
+    fn grant_allocated_count(&self) -> Option<usize> {
+        Some(self.grant.get().is_some() as usize)
+    }
+
+    fn lookup_grant_from_driver_num(&self, driver_num: usize) -> Result<usize, Error> {
+        if driver_num == console::DRIVER_NUM {
+            Ok(0)
+        } else {
+            Err(Error::AddressOutOfBounds)
+        }
+    }
+
+    fn is_valid_upcall_function_pointer(&self, _upcall_fn: NonNull<()>) -> bool {
+        true
+    }
+
+    fn set_syscall_return_value(&self, _return_value: SyscallReturn) {}
+
+    fn set_process_function(&self, _callback: FunctionCall) {}
+
+    fn switch_to(&self) -> Option<syscall::ContextSwitchReason> {
+        None
+    }
+
+    fn get_addresses(&self) -> ProcessAddresses {
+        unimplemented!()
+    }
+
+    fn get_sizes(&self) -> ProcessSizes {
+        unimplemented!()
+    }
+
+    fn print_memory_map(&self, _writer: &mut dyn Write) {}
+
+    fn print_full_process(&self, _writer: &mut dyn Write) {}
+
+    fn debug_syscall_count(&self) -> usize {
+        0
+    }
+
+    fn debug_dropped_upcall_count(&self) -> usize {
+        0
+    }
+
+    fn debug_timeslice_expiration_count(&self) -> usize {
+        0
+    }
+
+    fn debug_timeslice_expired(&self) {}
+
+    fn debug_syscall_called(&self, _last_syscall: Syscall) {}
+
+    fn debug_heap_start(&self) -> Option<*const u8> {
+        None
+    }
+
+    fn debug_stack_start(&self) -> Option<*const u8> {
+        None
+    }
+
+    fn debug_stack_end(&self) -> Option<*const u8> {
+        None
+    }
+}
+
+/// Upcalls the tests subscribe to: write done, read done and write progress.
+const WRITE_DONE: usize = 1;
+const READ_DONE: usize = 2;
+const WRITE_PROGRESS: usize = 4;
+
+struct Harness {
+    console: &'static Console<'static, MockUart, FakeAlarm>,
+    uart: &'static MockUart,
+    apps: Vec<&'static FakeProcess>,
+    log: Log,
+}
+
+fn leak<T>(value: T) -> &'static mut T {
+    Box::leak(Box::new(value))
+}
+
+impl Harness {
+    /// A console with transmit and receive buffers of `buf_len` bytes, used
+    /// by `num_apps` apps that have subscribed to the write, read and write
+    /// progress upcalls.
+    fn new(num_apps: usize, buf_len: usize) -> Harness {
+        let log: Log = leak(RefCell::new(Vec::new()));
+        let apps: Vec<&'static FakeProcess> = (0..num_apps)
+            .map(|index| {
+                &*leak(FakeProcess {
+                    index,
+                    log,
+                    processid: Cell::new(None),
+                    alive: Cell::new(true),
+                    grant: Cell::new(None),
+                    grant_entered: Cell::new(false),
+                })
+            })
+            .collect();
+        let processes: Vec<Option<&'static dyn Process>> = apps
+            .iter()
+            .map(|&app| Some(app as &'static dyn Process))
+            .collect();
+        let kernel: &'static Kernel = leak(Kernel::new(Box::leak(processes.into_boxed_slice())));
+        for app in &apps {
+            app.processid.set(Some(ProcessId::new_external(
+                kernel, app.index, app.index, &TestCap,
+            )));
+        }
+
+        let uart: &'static MockUart = leak(MockUart::new(log));
+        let alarm: &'static FakeAlarm = leak(FakeAlarm {
+            armed: Cell::new(false),
+            client: OptionalCell::empty(),
+        });
+        let console: &'static Console<'static, MockUart, FakeAlarm> = leak(Console::new(
+            uart,
+            alarm,
+            115200,
+            Box::leak(vec![0; buf_len].into_boxed_slice()),
+            Box::leak(vec![0; buf_len].into_boxed_slice()),
+            kernel.create_grant(console::DRIVER_NUM, &TestCap),
+        ));
+        uart::Transmit::set_transmit_client(uart, console);
+        uart::Receive::set_receive_client(uart, console);
+        alarm.set_alarm_client(console);
+
+        for app in &apps {
+            console.allocate_grant(app.processid()).unwrap();
+            for subscribe_num in [WRITE_DONE, READ_DONE, WRITE_PROGRESS] {
+                app.subscribe(subscribe_num);
+            }
+        }
+        Harness {
+            console,
+            uart,
+            apps,
+            log,
+        }
+    }
+
+    fn command(&self, app: usize, command_num: usize, arg1: usize, arg2: usize) -> CommandReturn {
+        self.console
+            .command(command_num, arg1, arg2, self.apps[app].processid())
+    }
+
+    /// Shares `data` with the console as `app`'s write buffer.
+    fn allow_write(&self, app: usize, data: &'static [u8]) {
+        let processid = self.apps[app].processid();
+        let buffer = unsafe {
+            ReadOnlyProcessBuffer::new_external(data.as_ptr(), data.len(), processid, &TestCap)
+        };
+        assert!(self.console.allow_readonly(processid, 1, buffer).is_ok());
+    }
+
+    /// Shares a read buffer of `len` bytes with the console for `app`, and
+    /// returns it.
+    fn allow_read(&self, app: usize, len: usize) -> &'static [u8] {
+        let processid = self.apps[app].processid();
+        let data = Box::leak(vec![0; len].into_boxed_slice());
+        let buffer = unsafe {
+            ReadWriteProcessBuffer::new_external(data.as_mut_ptr(), len, processid, &TestCap)
+        };
+        assert!(self.console.allow_readwrite(processid, 1, buffer).is_ok());
+        data
+    }
+
+    /// Starts a write of `data` from `app` with putstr.
+    fn write(&self, app: usize, data: &'static [u8]) {
+        self.allow_write(app, data);
+        assert!(self.command(app, 1, data.len(), 0).is_success());
+    }
+
+    /// Returns the events logged since the last call.
+    fn events(&self) -> Vec<Event> {
+        self.log.replace(Vec::new())
+    }
+}
+
+fn transmit(data: &[u8]) -> Event {
+    Event::Transmit(data.to_vec())
+}
+
+#[test]
+fn multi_chunk_write_reports_progress_then_completion() {
+    let h = Harness::new(1, 4);
+    h.write(0, b"abcdefghij");
+    assert_eq!(h.events(), [transmit(b"abcd")]);
+
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [
+            transmit(b"efgh"),
+            Event::Upcall(0, WRITE_PROGRESS, (4, 6, 0)),
+        ]
+    );
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [transmit(b"ij"), Event::Upcall(0, WRITE_PROGRESS, (8, 2, 0))]
+    );
+    // The write callback only comes once the last chunk is handed back.
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(0, WRITE_DONE, (10, 0, 0))]);
+}
+
+#[test]
+fn kernel_output_goes_ahead_of_queued_app_writes() {
+    let h = Harness::new(2, 4);
+    h.write(0, b"abcdef");
+    h.write(1, b"xyz");
+    assert!(h.console.print_static(b"KERN").is_ok());
+    assert_eq!(h.events(), [transmit(b"abcd")]);
+
+    // App 0's write in flight finishes first...
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [transmit(b"ef"), Event::Upcall(0, WRITE_PROGRESS, (4, 2, 0))]
+    );
+    // ...then the kernel message goes out, ahead of app 1's queued write.
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [Event::Upcall(0, WRITE_DONE, (6, 0, 0)), transmit(b"KERN")]
+    );
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [transmit(b"xyz")]);
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(1, WRITE_DONE, (3, 0, 0))]);
+}
+
+#[test]
+fn urgent_output_goes_ahead_of_kernel_messages() {
+    let h = Harness::new(1, 8);
+    assert!(h.console.print_static(b"first").is_ok());
+    assert_eq!(h.events(), [transmit(b"first")]);
+    assert!(h.console.print_static(b"second").is_ok());
+    // Only one kernel message can wait at a time.
+    assert_eq!(h.console.print_static(b"third"), Err(ErrorCode::BUSY));
+    assert!(h.console.print_urgent(b"URGENT").is_ok());
+
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [transmit(b"URGENT")]);
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [transmit(b"second")]);
+    h.uart.transmit_done();
+    assert_eq!(h.events(), []);
+}
+
+#[test]
+fn pending_writes_rotate_between_apps() {
+    let h = Harness::new(3, 4);
+    h.write(0, b"a0");
+    h.write(1, b"b1");
+    h.write(2, b"c2");
+    assert_eq!(h.events(), [transmit(b"a0")]);
+
+    // Each app that finishes makes way for the next in turn.
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [Event::Upcall(0, WRITE_DONE, (2, 0, 0)), transmit(b"b1")]
+    );
+    // App 0 queues again behind app 2, which has waited longer.
+    h.write(0, b"a3");
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [Event::Upcall(1, WRITE_DONE, (2, 0, 0)), transmit(b"c2")]
+    );
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [Event::Upcall(2, WRITE_DONE, (2, 0, 0)), transmit(b"a3")]
+    );
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(0, WRITE_DONE, (2, 0, 0))]);
+}
+
+#[test]
+fn read_completes_independently_of_a_write() {
+    let h = Harness::new(1, 4);
+    let read = h.allow_read(0, 3);
+    assert!(h.command(0, 2, 3, 0).is_success());
+    h.write(0, b"out");
+    assert_eq!(h.events(), [Event::Receive(3), transmit(b"out")]);
+
+    // The read completes while the write is still in flight.
+    h.uart.receive(b"in!");
+    assert_eq!(h.events(), [Event::Upcall(0, READ_DONE, (0, 3, 0))]);
+    assert_eq!(read, b"in!");
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(0, WRITE_DONE, (3, 0, 0))]);
+}
+
+#[test]
+fn rejected_writes_get_no_callback() {
+    let h = Harness::new(1, 4);
+    // No buffer shared yet.
+    assert_eq!(h.command(0, 1, 4, 0).get_failure(), Some(ErrorCode::INVAL));
+    h.write(0, b"abcdef");
+    // A second write while the first is under way.
+    assert_eq!(h.command(0, 1, 4, 0).get_failure(), Some(ErrorCode::BUSY));
+    assert_eq!(h.events(), [transmit(b"abcd")]);
+
+    // The accepted write still completes with one callback.
+    h.uart.transmit_done();
+    h.uart.transmit_done();
+    let events = h.events();
+    let write_callbacks = events
+        .iter()
+        .filter(|event| matches!(event, Event::Upcall(0, WRITE_DONE, _)))
+        .count();
+    assert_eq!(write_callbacks, 1);
+    assert_eq!(
+        events.last(),
+        Some(&Event::Upcall(0, WRITE_DONE, (6, 0, 0)))
+    );
+}
+
+#[test]
+fn write_failing_in_the_uart_gets_one_callback_with_the_error() {
+    let h = Harness::new(2, 4);
+    h.write(0, b"abcdefgh");
+    h.write(1, b"next");
+    assert_eq!(h.events(), [transmit(b"abcd")]);
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [
+            transmit(b"efgh"),
+            Event::Upcall(0, WRITE_PROGRESS, (4, 4, 0))
+        ]
+    );
+
+    // The UART fails the second chunk after sending one byte of it: the
+    // callback reports the five bytes that went out and the error, and the
+    // console moves on to the next app.
+    h.uart.transmit_done_with(1, Err(ErrorCode::FAIL));
+    assert_eq!(
+        h.events(),
+        [
+            Event::Upcall(
+                0,
+                WRITE_DONE,
+                (
+                    5,
+                    kernel::errorcode::into_statuscode(Err(ErrorCode::FAIL)),
+                    0
+                )
+            ),
+            transmit(b"next"),
+        ]
+    );
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(1, WRITE_DONE, (4, 0, 0))]);
+}
+
+#[test]
+fn transmit_refused_by_the_uart_fails_the_write_and_recovers() {
+    let h = Harness::new(2, 4);
+    // Refused up front: the command fails and there is no callback.
+    h.uart.tx_reject.set(Some(ErrorCode::OFF));
+    h.allow_write(0, b"abcdefgh");
+    assert_eq!(h.command(0, 1, 8, 0).get_failure(), Some(ErrorCode::OFF));
+    assert_eq!(h.events(), []);
+
+    // Refused partway: the write callback reports what went out and the
+    // error.
+    h.write(0, b"abcdefgh");
+    h.write(1, b"next");
+    assert_eq!(h.events(), [transmit(b"abcd")]);
+    h.uart.tx_reject.set(Some(ErrorCode::OFF));
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [
+            Event::Upcall(
+                0,
+                WRITE_DONE,
+                (
+                    4,
+                    kernel::errorcode::into_statuscode(Err(ErrorCode::OFF)),
+                    0
+                )
+            ),
+            transmit(b"next"),
+        ]
+    );
+
+    // The transmit buffer came back, so both apps can write again.
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(1, WRITE_DONE, (4, 0, 0))]);
+    h.write(0, b"more");
+    assert_eq!(h.events(), [transmit(b"more")]);
+}
+
+#[test]
+fn echo_goes_out_between_the_chunks_of_a_write() {
+    let h = Harness::new(1, 4);
+    h.allow_read(0, 2);
+    assert!(h.command(0, 15, 1, 0).is_success());
+    assert!(h.command(0, 2, 2, 0).is_success());
+    h.write(0, b"abcdefgh");
+    assert_eq!(h.events(), [Event::Receive(2), transmit(b"abcd")]);
+
+    // Bytes received while the first chunk is out wait for it, then go out
+    // before the rest of the write.
+    h.uart.receive(b"hi");
+    assert_eq!(h.events(), [Event::Upcall(0, READ_DONE, (0, 2, 0))]);
+    h.uart.transmit_done();
+    assert_eq!(
+        h.events(),
+        [Event::Upcall(0, WRITE_PROGRESS, (4, 4, 0)), transmit(b"hi")]
+    );
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [transmit(b"efgh")]);
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(0, WRITE_DONE, (8, 0, 0))]);
+}
+
+#[test]
+fn writer_dying_mid_transmit_releases_the_console() {
+    let h = Harness::new(3, 4);
+    // App 0 holds the transmitter for an atomic write; apps 1 and 2 queue.
+    h.allow_write(0, b"abcdefgh");
+    assert!(h.command(0, 1, 8, console::ATOMIC_WRITE).is_success());
+    h.write(1, b"one");
+    h.write(2, b"two");
+    assert_eq!(h.events(), [transmit(b"abcd")]);
+
+    // App 0 and app 1 are torn down while app 0's first chunk is out.
+    h.apps[0].alive.set(false);
+    h.apps[1].alive.set(false);
+    h.uart.transmit_done();
+    // Nobody is left to notify of either write; app 2 goes next.
+    assert_eq!(h.events(), [transmit(b"two")]);
+    h.uart.transmit_done();
+    assert_eq!(h.events(), [Event::Upcall(2, WRITE_DONE, (3, 0, 0))]);
+}
+
+#[test]
+fn empty_buffers_are_refused_when_used() {
+    let h = Harness::new(1, 4);
+    // Sharing an empty buffer is how an app revokes one, so `allow` takes it,
+    // but reads and writes from it are refused.
+    h.allow_write(0, b"");
+    h.allow_read(0, 0);
+    assert_eq!(h.command(0, 1, 4, 0).get_failure(), Some(ErrorCode::INVAL));
+    assert_eq!(h.command(0, 2, 4, 0).get_failure(), Some(ErrorCode::INVAL));
+    assert_eq!(h.events(), []);
+
+    // Once there is something to use they work again.
+    h.allow_read(0, 1);
+    assert!(h.command(0, 2, 4, 0).is_success());
+    h.write(0, b"ok");
+    assert_eq!(h.events(), [Event::Receive(1), transmit(b"ok")]);
+}
+
+#[test]
+fn address_filter_is_set_in_the_uart() {
+    let h = Harness::new(1, 4);
+    assert!(h.command(0, 7, 0x42, 1).is_success());
+    assert_eq!(h.events(), [Event::AddressMatch(Some(0x42))]);
+
+    // Frames the UART lets through are read as usual.
+    let data = h.allow_read(0, 2);
+    assert!(h.command(0, 2, 2, 0).is_success());
+    h.uart.receive(b"hi");
+    assert_eq!(
+        h.events(),
+        [Event::Receive(2), Event::Upcall(0, READ_DONE, (0, 2, 0))]
+    );
+    assert_eq!(data, b"hi");
+
+    assert!(h.command(0, 7, 0x42, 0).is_success());
+    assert_eq!(h.events(), [Event::AddressMatch(None)]);
+}
+
+#[test]
+fn tx_gap_is_bounded() {
+    let h = Harness::new(1, 4);
+    let max = console::MAX_TX_GAP_US as usize;
+    assert_eq!(
+        h.command(0, 42, max + 1, 0).get_failure(),
+        Some(ErrorCode::INVAL)
+    );
+    assert!(h.command(0, 42, max, 0).is_success());
+    assert!(h.command(0, 42, 0, 0).is_success());
+}
+
+#[test]
+fn break_without_a_read_is_reported_to_the_next_one() {
+    let h = Harness::new(1, 4);
+    assert!(h.command(0, 23, 1, 0).is_success());
+    assert_eq!(h.events(), [Event::Receive(1)]);
+    h.uart.receive(b"a");
+    h.uart.receive_failed(uart::Error::BreakError);
+    assert_eq!(h.events(), [Event::Receive(1), Event::Receive(1)]);
+
+    // The next read ends with the break, and the byte before it is kept.
+    h.allow_read(0, 4);
+    assert!(h.command(0, 2, 4, 0).is_success());
+    let status = kernel::errorcode::into_statuscode(Err(ErrorCode::NOACK));
+    assert_eq!(h.events(), [Event::Upcall(0, READ_DONE, (status, 0, 7))]);
+    assert_eq!(h.command(0, 38, 0, 0).get_success_u32(), Some(7));
+    assert_eq!(h.command(0, 44, 4, 0).get_success_u32(), Some(1));
+}
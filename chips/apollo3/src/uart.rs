@@ -293,6 +293,9 @@ impl Uart<'_> {
 
 impl hil::uart::Configure for Uart<'_> {
     fn configure(&self, params: hil::uart::Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
         let regs = self.registers;
 
         // Disable UART
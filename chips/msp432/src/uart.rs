@@ -144,6 +144,13 @@ impl<'a> dma::DmaClient for Uart<'a> {
 
 impl<'a> hil::uart::Configure for Uart<'a> {
     fn configure(&self, params: hil::uart::Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if params.width == hil::uart::Width::Six || params.width == hil::uart::Width::Nine {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+
         // Disable module
         let regs = self.registers;
         regs.ctlw0.modify(usci::UCAxCTLW0::UCSWRST::SET);
@@ -158,9 +165,8 @@ impl<'a> hil::uart::Configure for Uart<'a> {
         match params.width {
             hil::uart::Width::Eight => regs.ctlw0.modify(usci::UCAxCTLW0::UC7BIT::CLEAR),
             hil::uart::Width::Seven => regs.ctlw0.modify(usci::UCAxCTLW0::UC7BIT::SET),
-            hil::uart::Width::Six => {
-                panic!("UART: width of 6 bit is not supported by this hardware!")
-            }
+            // Rejected above.
+            hil::uart::Width::Six | hil::uart::Width::Nine => {}
         }
 
         // Setup stop bits
@@ -140,4 +140,8 @@ impl kernel::platform::watchdog::WatchDog for Wdt {
                 .modify(WDTCTL::WDTPW.val(PASSWORD) + WDTCTL::WDTCNTCL::SET);
         }
     }
+
+    fn is_running(&self) -> bool {
+        !self.registers.ctl.is_set(WDTCTL::WDTHOLD)
+    }
 }
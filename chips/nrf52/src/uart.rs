@@ -156,6 +156,33 @@ register_bitfields! [u32,
     ]
 ];
 
+/// Supported baud rates and the matching `BAUDRATE` register settings.
+const BAUD_RATES: [(u32, u32); 16] = [
+    (1200, 0x0004F000),
+    (2400, 0x0009D000),
+    (4800, 0x0013B000),
+    (9600, 0x00275000),
+    (14400, 0x003AF000),
+    (19200, 0x004EA000),
+    (28800, 0x0075C000),
+    (38400, 0x009D0000),
+    (57600, 0x00EB0000),
+    (76800, 0x013A9000),
+    (115200, 0x01D60000),
+    (230400, 0x03B00000),
+    (250000, 0x04000000),
+    (460800, 0x07400000),
+    (921600, 0x0F000000),
+    (1000000, 0x10000000),
+];
+
+/// The baud rate a `BAUDRATE` register setting actually generates, which is
+/// approximately `setting * 16 MHz / 2^32`, see the BAUDRATE register
+/// description in the datasheet.
+fn generated_baud_rate(setting: u32) -> u32 {
+    ((setting as u64 * 16_000_000 + (1 << 31)) >> 32) as u32
+}
+
 /// UARTE
 // It should never be instanced outside this module but because a static mutable reference to it
 // is exported outside this module it must be `pub`
@@ -236,26 +263,18 @@ impl<'a> Uarte<'a> {
         self.enable_uart();
     }
 
-    fn set_baud_rate(&self, baud_rate: u32) {
-        match baud_rate {
-            1200 => self.registers.baudrate.set(0x0004F000),
-            2400 => self.registers.baudrate.set(0x0009D000),
-            4800 => self.registers.baudrate.set(0x0013B000),
-            9600 => self.registers.baudrate.set(0x00275000),
-            14400 => self.registers.baudrate.set(0x003AF000),
-            19200 => self.registers.baudrate.set(0x004EA000),
-            28800 => self.registers.baudrate.set(0x0075C000),
-            38400 => self.registers.baudrate.set(0x009D0000),
-            57600 => self.registers.baudrate.set(0x00EB0000),
-            76800 => self.registers.baudrate.set(0x013A9000),
-            115200 => self.registers.baudrate.set(0x01D60000),
-            230400 => self.registers.baudrate.set(0x03B00000),
-            250000 => self.registers.baudrate.set(0x04000000),
-            460800 => self.registers.baudrate.set(0x07400000),
-            921600 => self.registers.baudrate.set(0x0F000000),
-            1000000 => self.registers.baudrate.set(0x10000000),
-            _ => self.registers.baudrate.set(0x01D60000), //setting default to 115200
-        }
+    /// Sets the baud rate to one of `BAUD_RATES`, given either as its nominal
+    /// rate or as the rate it actually generates (what `nearest_baud_rate`
+    /// reports). Returns INVAL for any other rate.
+    fn set_baud_rate(&self, baud_rate: u32) -> Result<(), ErrorCode> {
+        let (_, setting) = BAUD_RATES
+            .iter()
+            .find(|(rate, setting)| {
+                *rate == baud_rate || generated_baud_rate(*setting) == baud_rate
+            })
+            .ok_or(ErrorCode::INVAL)?;
+        self.registers.baudrate.set(*setting);
+        Ok(())
     }
 
     // Enable UART peripheral, this need to disabled for low power applications
@@ -478,10 +497,23 @@ impl<'a> uart::Configure for Uarte<'a> {
         if params.hw_flow_control != false {
             return Err(ErrorCode::NOSUPPORT);
         }
+        // The UARTE only sends 8-bit characters.
+        if params.width != uart::Width::Eight {
+            return Err(ErrorCode::NOSUPPORT);
+        }
 
-        self.set_baud_rate(params.baud_rate);
+        self.set_baud_rate(params.baud_rate)
+    }
 
-        Ok(())
+    fn nearest_baud_rate(&self, baud_rate: u32) -> Result<u32, ErrorCode> {
+        if baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        let (_, setting) = BAUD_RATES
+            .iter()
+            .min_by_key(|(rate, _)| (*rate as i64 - baud_rate as i64).abs())
+            .unwrap_or(&BAUD_RATES[0]);
+        Ok(generated_baud_rate(*setting))
     }
 }
 
@@ -506,10 +506,25 @@ impl<'a> Uart<'a> {
 
         if self.registers.uartimsc.is_set(UARTIMSC::RXIM) {
             if self.registers.uartfr.is_set(UARTFR::RXFF) {
-                let byte = self.registers.uartdr.get() as u8;
+                let data = self.registers.uartdr.extract();
+                let byte = data.read(UARTDR::DATA) as u8;
 
                 self.disable_receive_interrupt();
-                if self.rx_status.get() == UARTStateRX::Receiving {
+                if self.rx_status.get() == UARTStateRX::Receiving && data.is_set(UARTDR::BE) {
+                    // A break arrives as a zero character flagged with BE. It
+                    // is not data: end the receive with what came before it.
+                    self.rx_status.replace(UARTStateRX::Idle);
+                    self.rx_client.map(|client| {
+                        if let Some(buf) = self.rx_buffer.take() {
+                            client.received_buffer(
+                                buf,
+                                self.rx_position.get(),
+                                Err(ErrorCode::FAIL),
+                                hil::uart::Error::BreakError,
+                            );
+                        }
+                    });
+                } else if self.rx_status.get() == UARTStateRX::Receiving {
                     if self.rx_position.get() < self.rx_len.get() {
                         self.rx_buffer.map(|buf| {
                             buf[self.rx_position.get()] = byte;
@@ -577,6 +592,12 @@ impl<'a> Uart<'a> {
 
 impl Configure for Uart<'_> {
     fn configure(&self, params: Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if params.width == Width::Nine {
+            return Err(ErrorCode::NOSUPPORT);
+        }
         self.disable();
         self.registers.uartlcr_h.modify(UARTLCR_H::FEN::CLEAR);
 
@@ -610,6 +631,8 @@ impl Configure for Uart<'_> {
             Width::Six => self.registers.uartlcr_h.modify(UARTLCR_H::WLEN::BITS_6),
             Width::Seven => self.registers.uartlcr_h.modify(UARTLCR_H::WLEN::BITS_7),
             Width::Eight => self.registers.uartlcr_h.modify(UARTLCR_H::WLEN::BITS_8),
+            // Rejected above.
+            Width::Nine => {}
         }
 
         // Configure parity
@@ -659,6 +682,14 @@ impl Configure for Uart<'_> {
 
         Ok(())
     }
+
+    fn set_loopback(&self, enabled: bool) -> Result<bool, ErrorCode> {
+        let previous = self.registers.uartcr.is_set(UARTCR::LBE);
+        self.registers
+            .uartcr
+            .modify(UARTCR::LBE.val(enabled as u32));
+        Ok(previous)
+    }
 }
 
 impl<'a> Transmit<'a> for Uart<'a> {
@@ -735,4 +766,15 @@ impl<'a> Receive<'a> for Uart<'a> {
             Ok(())
         }
     }
+
+    fn receive_flush(&self) -> Result<(), ErrorCode> {
+        if self.rx_status.get() != UARTStateRX::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        // Empty the receive FIFO.
+        while !self.registers.uartfr.is_set(UARTFR::RXFE) {
+            let _ = self.registers.uartdr.get();
+        }
+        Ok(())
+    }
 }
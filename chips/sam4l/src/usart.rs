@@ -10,7 +10,9 @@ use kernel::hil::spi;
 use kernel::hil::uart;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
-use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
+use kernel::utilities::registers::{
+    register_bitfields, FieldValue, ReadOnly, ReadWrite, WriteOnly,
+};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 
@@ -391,6 +393,13 @@ pub struct USART<'a> {
     tx_dma_peripheral: dma::DMAPeripheral,
     tx_len: Cell<usize>,
 
+    // Multidrop address matching: the node address, if set, whether the
+    // frame being received is addressed to it, and the parity to go back to
+    // when matching is turned off.
+    address_match: OptionalCell<u8>,
+    addressed: Cell<bool>,
+    parity: Cell<uart::Parity>,
+
     client: OptionalCell<UsartClient<'a>>,
 
     spi_chip_select: OptionalCell<&'a dyn hil::gpio::Pin>,
@@ -422,6 +431,10 @@ impl<'a> USART<'a> {
             tx_dma_peripheral: tx_dma_peripheral,
             tx_len: Cell::new(0),
 
+            address_match: OptionalCell::empty(),
+            addressed: Cell::new(false),
+            parity: Cell::new(uart::Parity::None),
+
             // this gets defined later by `main.rs`
             client: OptionalCell::empty(),
 
@@ -542,6 +555,53 @@ impl<'a> USART<'a> {
         }
     }
 
+    /// Receives into the buffer of the reception in progress again from the
+    /// start, dropping what it holds.
+    fn restart_rx(&self) {
+        self.rx_dma.get().map(|rx_dma| {
+            rx_dma
+                .abort_transfer()
+                .map(|buf| rx_dma.do_transfer(self.rx_dma_peripheral, buf, self.rx_len.get()));
+        });
+    }
+
+    /// Handles an address character in multidrop mode. It ends the frame
+    /// received so far, which goes to the client if it was addressed to this
+    /// node and is dropped otherwise, and starts the next one.
+    fn address_received(&self, usart: &USARTRegManager) {
+        let address = usart.registers.rhr.read(ReceiverHold::RXCHR) as u8;
+        let addressed = self
+            .addressed
+            .replace(self.address_match.contains(&address));
+        if self.usart_rx_state.get() != USARTStateRX::DMA_Receiving {
+            return;
+        }
+
+        // The PDC has already moved the address character into the buffer,
+        // right after the frame it ends.
+        let length = self.rx_dma.get().map_or(0, |rx_dma| {
+            (self.rx_len.get() - rx_dma.transfer_counter()).saturating_sub(1)
+        });
+        if !addressed || length == 0 {
+            self.restart_rx();
+            return;
+        }
+
+        self.disable_rx_interrupts(usart);
+        self.disable_rx(usart);
+        let buffer = self.rx_dma.get().and_then(|rx_dma| {
+            let buf = rx_dma.abort_transfer();
+            rx_dma.disable();
+            buf
+        });
+        self.rx_len.set(0);
+        self.client.map(|usartclient| {
+            if let UsartClient::Uart(Some(rx), _tx) = usartclient {
+                buffer.map(|buf| rx.received_buffer(buf, length, Ok(()), uart::Error::None));
+            }
+        });
+    }
+
     fn abort_tx(&self, usart: &USARTRegManager, rcode: Result<(), ErrorCode>) {
         if self.usart_tx_state.get() == USARTStateTX::DMA_Transmitting {
             self.disable_tx_interrupts(usart);
@@ -687,6 +747,9 @@ impl<'a> USART<'a> {
                     }
                 }
             });
+        } else if status.is_set(ChannelStatus::PARE) && self.address_match.is_some() {
+            // In multidrop mode the parity bit marks address characters.
+            self.address_received(usart);
         } else if status.is_set(ChannelStatus::PARE) {
             self.abort_rx(usart, Err(ErrorCode::FAIL), uart::Error::ParityError);
         } else if status.is_set(ChannelStatus::FRAME) {
@@ -777,6 +840,11 @@ impl dma::DMAClient for USART<'_> {
                 // determine if it was an RX or TX transfer
                 if pid == self.rx_dma_peripheral {
                     // RX transfer was completed
+                    if self.address_match.is_some() && !self.addressed.get() {
+                        // The bytes are part of a frame for another node.
+                        self.restart_rx();
+                        return;
+                    }
 
                     // disable RX and RX interrupts
                     self.disable_rx_interrupts(usart);
@@ -943,13 +1011,25 @@ impl uart::Configure for USART<'_> {
         if self.usart_mode.get() != UsartMode::Uart {
             return Err(ErrorCode::OFF);
         }
+        if parameters.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        // 9-bit characters do not fit the byte-wide PDC transfers.
+        if parameters.width == uart::Width::Nine {
+            return Err(ErrorCode::NOSUPPORT);
+        }
 
         let usart = &USARTRegManager::new(&self);
 
         // set USART mode register
         let mut mode = Mode::OVER::SET; // OVER: oversample at 8x
 
-        mode += Mode::CHRL::BITS8; // CHRL: 8-bit characters
+        mode += match parameters.width {
+            uart::Width::Six => Mode::CHRL::BITS6,
+            uart::Width::Seven => Mode::CHRL::BITS7,
+            // Nine is rejected above.
+            uart::Width::Eight | uart::Width::Nine => Mode::CHRL::BITS8,
+        };
         mode += Mode::USCLKS::CLK_USART; // USCLKS: select CLK_USART
 
         mode += match parameters.stop_bits {
@@ -957,10 +1037,11 @@ impl uart::Configure for USART<'_> {
             uart::StopBits::Two => Mode::NBSTOP::BITS_2_2,
         };
 
-        mode += match parameters.parity {
-            uart::Parity::None => Mode::PAR::NONE, // no parity
-            uart::Parity::Odd => Mode::PAR::ODD,   // odd parity
-            uart::Parity::Even => Mode::PAR::EVEN, // even parity
+        self.parity.set(parameters.parity);
+        mode += if self.address_match.is_some() {
+            Mode::PAR::MULTID
+        } else {
+            parity_mode(parameters.parity)
         };
 
         mode += match parameters.hw_flow_control {
@@ -973,6 +1054,36 @@ impl uart::Configure for USART<'_> {
 
         Ok(())
     }
+
+    fn set_address_match(&self, address: Option<u8>) -> Result<(), ErrorCode> {
+        if self.usart_mode.get() != UsartMode::Uart {
+            return Err(ErrorCode::OFF);
+        }
+        let usart = &USARTRegManager::new(&self);
+
+        // Multidrop mode carries the address bit in place of the parity bit,
+        // so parity is off while matching.
+        match address {
+            Some(address) => {
+                self.address_match.set(address);
+                usart.registers.mr.modify(Mode::PAR::MULTID);
+            }
+            None => {
+                self.address_match.clear();
+                usart.registers.mr.modify(parity_mode(self.parity.get()));
+            }
+        }
+        self.addressed.set(false);
+        Ok(())
+    }
+}
+
+fn parity_mode(parity: uart::Parity) -> FieldValue<u32, Mode::Register> {
+    match parity {
+        uart::Parity::None => Mode::PAR::NONE, // no parity
+        uart::Parity::Odd => Mode::PAR::ODD,   // odd parity
+        uart::Parity::Even => Mode::PAR::EVEN, // even parity
+    }
 }
 
 impl<'a> uart::ReceiveAdvanced<'a> for USART<'a> {
@@ -236,4 +236,10 @@ impl kernel::platform::watchdog::WatchDog for Wdt {
     fn suspend(&self) {
         self.stop();
     }
+
+    fn is_running(&self) -> bool {
+        // The registers are only readable while the WDT clock is on, which
+        // it is whenever `enabled` is set.
+        self.enabled.get() && WDT_REGS.cr.is_set(Control::EN)
+    }
 }
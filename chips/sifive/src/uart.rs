@@ -170,6 +170,9 @@ impl<'a> Uart<'a> {
 
 impl hil::uart::Configure for Uart<'_> {
     fn configure(&self, params: hil::uart::Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
         // This chip does not support these features.
         if params.parity != hil::uart::Parity::None {
             return Err(ErrorCode::NOSUPPORT);
@@ -68,6 +68,8 @@ impl<'a> InterruptService<DeferredCallTask> for Stm32f3xxDefaultPeripherals<'a>
             nvic::I2C1_ER => self.i2c1.handle_error(),
             nvic::ADC1_2 => self.adc1.handle_interrupt(),
 
+            nvic::WWDG => self.watchdog.handle_interrupt(),
+
             nvic::EXTI0 => self.exti.handle_interrupt(),
             nvic::EXTI1 => self.exti.handle_interrupt(),
             nvic::EXTI2 => self.exti.handle_interrupt(),
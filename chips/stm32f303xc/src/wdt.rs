@@ -2,10 +2,13 @@
 
 use crate::rcc;
 use core::cell::Cell;
+use kernel::hil;
 use kernel::platform::chip::ClockInterface;
-use kernel::utilities::registers::interfaces::ReadWriteable;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 const WINDOW_WATCHDOG_BASE: StaticRef<WwdgRegisters> =
     unsafe { StaticRef::new(0x4000_2C00 as *const WwdgRegisters) };
@@ -64,6 +67,7 @@ pub struct WindoWdg<'a> {
     registers: StaticRef<WwdgRegisters>,
     clock: WdgClock<'a>,
     enabled: Cell<bool>,
+    client: OptionalCell<&'a dyn hil::watchdog::PreTimeoutClient>,
 }
 
 impl<'a> WindoWdg<'a> {
@@ -75,6 +79,7 @@ impl<'a> WindoWdg<'a> {
                 rcc,
             )),
             enabled: Cell::new(false),
+            client: OptionalCell::empty(),
         }
     }
 
@@ -123,6 +128,12 @@ impl<'a> WindoWdg<'a> {
         // Uses 63 as the value the watchdog starts counting from.
         self.registers.cr.modify(Control::T.val(0x7F));
     }
+
+    pub fn handle_interrupt(&self) {
+        // The early wakeup interrupt fires one counter tick before the reset.
+        self.registers.sr.write(Status::EWIF::CLEAR);
+        self.client.map(|client| client.pre_timeout());
+    }
 }
 
 struct WdgClock<'a>(rcc::PeripheralClock<'a>);
@@ -165,4 +176,24 @@ impl<'a> kernel::platform::watchdog::WatchDog for WindoWdg<'a> {
             self.clock.enable();
         }
     }
+
+    fn is_running(&self) -> bool {
+        // Suspending stops the watchdog's clock rather than the watchdog.
+        self.enabled.get() && self.clock.is_enabled() && self.registers.cr.is_set(Control::WDGA)
+    }
+}
+
+impl<'a> hil::watchdog::PreTimeout<'a> for WindoWdg<'a> {
+    fn set_client(&self, client: &'a dyn hil::watchdog::PreTimeoutClient) {
+        self.client.set(client);
+    }
+
+    fn enable_pre_timeout(&self) -> Result<(), ErrorCode> {
+        if !self.enabled.get() {
+            return Err(ErrorCode::OFF);
+        }
+        // Only cleared by hardware after a reset.
+        self.registers.cfr.modify(Config::EWI::SET);
+        Ok(())
+    }
 }
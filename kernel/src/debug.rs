@@ -58,6 +58,7 @@ use crate::collections::queue::Queue;
 use crate::collections::ring_buffer::RingBuffer;
 use crate::hil;
 use crate::platform::chip::Chip;
+use crate::platform::watchdog::WatchDog;
 use crate::process::Process;
 use crate::utilities::cells::NumericCellExt;
 use crate::utilities::cells::{MapCell, TakeCell};
@@ -107,6 +108,8 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip>(
     chip: &'static Option<&'static C>,
 ) {
     panic_begin(nop);
+    panic_tickle_watchdog();
+    let writer = &mut PanicWatchdogWriter { writer: writer };
     panic_banner(writer, panic_info);
     // Flush debug buffer if needed
     flush(writer);
@@ -114,6 +117,53 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip>(
     panic_process_info(processes, writer);
 }
 
+/// Watchdog that the panic routines keep servicing while they print.
+static mut PANIC_WATCHDOG: Option<&'static dyn WatchDog> = None;
+
+/// Function used by board main.rs to have the panic routines service
+/// `watchdog` while they print, so that a long panic dump is not cut short by
+/// a watchdog reset. Once the output is done the watchdog is left alone, so
+/// it still resets a board stuck in its panic loop.
+pub unsafe fn set_panic_watchdog(watchdog: &'static dyn WatchDog) {
+    PANIC_WATCHDOG = Some(watchdog);
+}
+
+/// Services the watchdog registered with `set_panic_watchdog`, if any.
+unsafe fn panic_tickle_watchdog() {
+    PANIC_WATCHDOG.map(|watchdog| watchdog.tickle_from_panic());
+}
+
+/// Largest number of bytes the panic writer sends between two watchdog
+/// services, about 5.5 ms at 115200 baud.
+const PANIC_WATCHDOG_CHUNK: usize = 64;
+
+/// Wraps the panic writer so that the watchdog is serviced as the panic
+/// output goes out, not only between its sections: at 115200 baud a single
+/// process dump takes longer than a short watchdog timeout such as the
+/// stm32f3's WWDG.
+struct PanicWatchdogWriter<'a, W> {
+    writer: &'a mut W,
+}
+
+impl<W: Write> Write for PanicWatchdogWriter<'_, W> {
+    fn write_str(&mut self, s: &str) -> Result {
+        // Panic output is formatted a line or a field at a time.
+        let result = self.writer.write_str(s);
+        unsafe { panic_tickle_watchdog() };
+        result
+    }
+}
+
+impl<W: IoWrite> IoWrite for PanicWatchdogWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) {
+        // Flushed debug buffers can be much longer than a line.
+        for chunk in buf.chunks(PANIC_WATCHDOG_CHUNK) {
+            self.writer.write(chunk);
+            unsafe { panic_tickle_watchdog() };
+        }
+    }
+}
+
 /// Tock default panic routine.
 ///
 /// **NOTE:** The supplied `writer` must be synchronous.
@@ -185,6 +235,7 @@ pub unsafe fn panic_process_info<W: Write>(
     for idx in 0..procs.len() {
         procs[idx].as_ref().map(|process| {
             process.print_full_process(writer);
+            panic_tickle_watchdog();
         });
     }
 }
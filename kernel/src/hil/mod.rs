@@ -30,6 +30,7 @@ pub mod touch;
 pub mod uart;
 pub mod usb;
 pub mod usb_hid;
+pub mod watchdog;
 
 /// Shared interface for configuring components.
 pub trait Controller {
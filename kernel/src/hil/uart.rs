@@ -22,6 +22,11 @@ pub enum Width {
     Six = 6,
     Seven = 7,
     Eight = 8,
+    /// Nine data bits, as used by multidrop buses where the ninth bit marks
+    /// address frames. The data path stays byte-oriented: the ninth bit is
+    /// handled by the UART (e.g. for address matching) and is not passed
+    /// through `Transmit` and `Receive`.
+    Nine = 9,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -56,6 +61,10 @@ pub enum Error {
 
     /// Read or write was aborted early
     Aborted,
+
+    /// Break condition (line held low for longer than a character frame)
+    /// detected during receive
+    BreakError,
 }
 
 pub trait Uart<'a>: Configure + Transmit<'a> + Receive<'a> {}
@@ -78,6 +87,36 @@ pub trait Configure {
     /// - INVAL: Impossible parameters (e.g. a `baud_rate` of 0)
     /// - ENOSUPPORT: The underlying UART cannot satisfy this configuration.
     fn configure(&self, params: Parameters) -> Result<(), ErrorCode>;
+
+    /// Returns the baud rate closest to `baud_rate` that the UART can
+    /// actually generate from its peripheral clock, without changing the
+    /// current configuration. Returns the achievable rate, or
+    /// - INVAL: Impossible parameters (e.g. a `baud_rate` of 0)
+    /// - NOSUPPORT: The underlying UART cannot report achievable rates.
+    fn nearest_baud_rate(&self, _baud_rate: u32) -> Result<u32, ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Restricts reception to frames addressed to `address` on a 9-bit
+    /// multidrop bus, using the UART's address-match hardware. Frames for
+    /// other nodes are dropped before they reach the receive client, and a
+    /// reception ends where the next frame starts. Address characters are
+    /// not delivered. `None` turns filtering off. Returns Ok(()), or
+    /// - OFF: The underlying hardware is currently not available, as for
+    ///         `configure`.
+    /// - NOSUPPORT: The underlying UART has no 9-bit address matching.
+    fn set_address_match(&self, _address: Option<u8>) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Connects the UART's transmitter internally to its receiver, so that
+    /// everything transmitted is received back and nothing reaches the line,
+    /// or disconnects them again. Meant for self-tests. Returns whether
+    /// loopback was enabled before the call, or
+    /// - NOSUPPORT: The underlying UART has no loopback mode.
+    fn set_loopback(&self, _enabled: bool) -> Result<bool, ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
 
 pub trait Transmit<'a> {
@@ -209,6 +248,16 @@ pub trait Receive<'a> {
     /// not cancelled successfully, then `FAIL` will be returned and
     /// there will be a later callback.
     fn receive_abort(&self) -> Result<(), ErrorCode>;
+
+    /// Discards data the UART has received but not yet handed to a client,
+    /// such as bytes waiting in a hardware FIFO, so that the next receive
+    /// only gets data that arrives after the call. There is no callback.
+    /// Returns Ok(()), or
+    /// - BUSY: A receive is outstanding; abort it first.
+    /// - NOSUPPORT: The underlying UART cannot discard received data.
+    fn receive_flush(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
 
 /// Trait implemented by a UART transmitter to receive callbacks when
@@ -0,0 +1,29 @@
+//! Interface for watchdog timers that can warn before they reset the chip.
+//!
+//! The kernel itself services the watchdog through
+//! [`crate::platform::watchdog::WatchDog`]. This interface is for watchdogs
+//! that can additionally raise an interrupt shortly before they expire, so
+//! that a capsule can react before the reset happens.
+
+use crate::ErrorCode;
+
+/// A watchdog timer with a pre-timeout (early warning) interrupt.
+pub trait PreTimeout<'a> {
+    /// Set the client to be notified when the pre-timeout interrupt fires.
+    fn set_client(&self, client: &'a dyn PreTimeoutClient);
+
+    /// Enable the pre-timeout interrupt.
+    ///
+    /// Returns `Err(ErrorCode::OFF)` if the watchdog is not in use on this
+    /// board. On some hardware the interrupt cannot be disabled again until
+    /// the next reset, so there is no matching `disable` function.
+    fn enable_pre_timeout(&self) -> Result<(), ErrorCode>;
+}
+
+/// Client for watchdog pre-timeout notifications.
+pub trait PreTimeoutClient {
+    /// Called when the watchdog is about to expire.
+    ///
+    /// How much time remains before the reset is hardware specific.
+    fn pre_timeout(&self);
+}
@@ -80,7 +80,11 @@ pub trait KernelResources<C: Chip> {
 ///
 /// ```ignore
 /// struct Hail {
-///     console: &'static capsules::console::Console<'static>,
+///     console: &'static capsules::console::Console<
+///         'static,
+///         capsules::virtual_uart::UartDevice<'static>,
+///         capsules::virtual_alarm::VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+///     >,
 ///     ipc: kernel::ipc::IPC,
 ///     dac: &'static capsules::dac::Dac<'static>,
 /// }
@@ -29,6 +29,25 @@ pub trait WatchDog {
     fn resume(&self) {
         self.tickle();
     }
+
+    /// Returns whether the watchdog is currently running, that is, whether it
+    /// will reset the chip unless it is tickled. Implementations should check
+    /// the hardware rather than only their own bookkeeping. The default, for
+    /// platforms without a watchdog, always returns `false`.
+    fn is_running(&self) -> bool {
+        false
+    }
+
+    /// Services the watchdog from the panic handler, so that it does not
+    /// reset the chip while panic output is still being printed. Unlike
+    /// `tickle()`, this must never start a watchdog that is not running, and
+    /// it must be safe to call with the kernel in an arbitrary state: no
+    /// grants, no allocation and no waiting on interrupts.
+    fn tickle_from_panic(&self) {
+        if self.is_running() {
+            self.tickle();
+        }
+    }
 }
 
 /// Implement default WatchDog trait for unit.
@@ -153,6 +153,40 @@ impl CommandReturn {
     pub fn success_u64_u32(data0: u64, data1: u32) -> Self {
         CommandReturn(SyscallReturn::SuccessU64U32(data0, data1))
     }
+
+    /// Returns true if the command succeeded, whatever data it returned.
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self.0,
+            SyscallReturn::Success
+                | SyscallReturn::SuccessU32(_)
+                | SyscallReturn::SuccessU32U32(_, _)
+                | SyscallReturn::SuccessU32U32U32(_, _, _)
+                | SyscallReturn::SuccessU64(_)
+                | SyscallReturn::SuccessU64U32(_, _)
+        )
+    }
+
+    /// Returns the error code if the command failed, whatever data it
+    /// returned.
+    pub fn get_failure(&self) -> Option<ErrorCode> {
+        match self.0 {
+            SyscallReturn::Failure(rc)
+            | SyscallReturn::FailureU32(rc, _)
+            | SyscallReturn::FailureU32U32(rc, _, _)
+            | SyscallReturn::FailureU64(rc, _) => Some(rc),
+            _ => None,
+        }
+    }
+
+    /// Returns the data of a command that succeeded with a single 32-bit
+    /// value.
+    pub fn get_success_u32(&self) -> Option<u32> {
+        match self.0 {
+            SyscallReturn::SuccessU32(data0) => Some(data0),
+            _ => None,
+        }
+    }
 }
 
 impl From<Result<(), ErrorCode>> for CommandReturn {